@@ -10,6 +10,8 @@ type Link<T> = Option<Rc<Node<T>>>;
 struct Node<T> {
     elem: T,
     next: Link<T>,
+    /// The length of the list starting at this node, so that it doesn't need to be walked to be counted.
+    len: usize,
 }
 
 impl<T> List<T> {
@@ -20,13 +22,7 @@ impl<T> List<T> {
 
     #[must_use]
     pub fn len(&self) -> usize {
-        let mut len = 0;
-        let mut me = &self.head;
-        while let Some(node_rc) = me {
-            me = &node_rc.as_ref().next;
-            len += 1;
-        }
-        len
+        self.head.as_ref().map_or(0, |node| node.len)
     }
 
     #[must_use]
@@ -37,15 +33,16 @@ impl<T> List<T> {
     #[must_use]
     pub fn push(&self, elem: T) -> Self {
         let next = self.head.clone();
+        let len = self.len() + 1;
         Self {
-            head: Some(Rc::new(Node { elem, next })),
+            head: Some(Rc::new(Node { elem, next, len })),
         }
     }
 
     #[must_use]
     fn split(&self) -> Option<(&T, &Link<T>)> {
         self.head.as_ref().map(|x| {
-            let Node { elem, next } = x.as_ref();
+            let Node { elem, next, .. } = x.as_ref();
             (elem, next)
         })
     }
@@ -66,10 +63,48 @@ impl<T> List<T> {
     pub const fn iter(&self) -> Iter<'_, T> {
         Iter { link: &self.head }
     }
+
+    #[must_use]
+    pub fn contains(&self, x: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.iter().any(|elem| elem == x)
+    }
+
+    /// Returns a new list of `f` applied to each element, in the same order. This takes O(n) time, allocating a new node for every element as well as a temporary buffer, since the list can only be built from the tail up.
+    #[must_use]
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> List<U> {
+        List::from_vec(self.iter().map(f).collect())
+    }
+
+    /// Returns a new list of clones of the elements satisfying `pred`, in the same order. This takes O(n) time, allocating a new node for every kept element as well as a temporary buffer, since the list can only be built from the tail up.
+    #[must_use]
+    pub fn filter(&self, pred: impl Fn(&T) -> bool) -> Self
+    where
+        T: Clone,
+    {
+        Self::from_vec(self.iter().filter(|elem| pred(elem)).cloned().collect())
+    }
+
+    /// Builds a list whose head is the first element of the vector.
+    fn from_vec(vec: Vec<T>) -> Self {
+        vec.into_iter()
+            .rev()
+            .fold(Self::new(), |list, elem| list.push(elem))
+    }
+
+    /// Returns the index of the first element equal to `x`, counting from the head.
+    #[must_use]
+    pub fn position(&self, x: &T) -> Option<usize>
+    where
+        T: PartialEq,
+    {
+        self.iter().position(|elem| elem == x)
+    }
 }
 
 impl<T> Default for List<T> {
-    #[must_use]
     fn default() -> Self {
         Self::new()
     }
@@ -107,6 +142,43 @@ mod tests {
         assert!(list.cdr().cdr().cdr().cdr().car().is_none());
     }
 
+    #[test]
+    fn cached_len() {
+        let mut list = List::new();
+        for i in 0..1000 {
+            list = list.push(i);
+        }
+        assert_eq!(list.len(), 1000);
+        assert_eq!(list.cdr().len(), 999);
+        assert_eq!(list.cdr().push(5).push(6).len(), 1001);
+        // The original list is unaffected by pushing onto its tail.
+        assert_eq!(list.len(), 1000);
+        assert_eq!(List::<i32>::new().cdr().len(), 0);
+    }
+
+    #[test]
+    fn membership() {
+        let list = List::new().push(1).push(4).push(9).push(16);
+        assert!(list.contains(&9));
+        assert!(!list.contains(&5));
+        assert_eq!(list.position(&16), Some(0));
+        assert_eq!(list.position(&1), Some(3));
+        assert_eq!(list.position(&5), None);
+    }
+
+    #[test]
+    fn mapping_and_filtering() {
+        let list = List::new().push(1).push(4).push(9).push(16);
+        let doubled = list.map(|x| x * 2);
+        assert_eq!(doubled.iter().copied().collect::<Vec<_>>(), [32, 18, 8, 2]);
+        assert_eq!(doubled.len(), 4);
+        let even = list.filter(|x| x % 2 == 0);
+        assert_eq!(even.iter().copied().collect::<Vec<_>>(), [16, 4]);
+        assert_eq!(even.len(), 2);
+        assert!(list.filter(|_| false).is_empty());
+        assert!(List::<i32>::new().map(|x| x + 1).is_empty());
+    }
+
     #[test]
     fn iterating() {
         let list = List::new().push(1).push(4).push(9).push(16);
@@ -0,0 +1,455 @@
+//! A compact binary format for compiled code, so that programs can be run without parsing and compiling them again.
+//!
+//! A file starts with [`MAGIC`], followed by the identifier table and then the main [`Code`] object. Every count, index, and length is written as an unsigned LEB128 varint. Only what the compiler produces is stored; the captures and memo of a [`Code`] are filled in at runtime, so they are left out.
+
+use super::{Code, Intrinsic, Op, TinyInt, Value, INTRINSIC_IDENTS};
+use crate::parse::Ident;
+use indexmap::IndexSet;
+use num_bigint::BigInt;
+use std::fmt;
+use std::rc::Rc;
+
+/// The bytes every bytecode file starts with. The last byte is the format version, which changes whenever the encoding does.
+pub const MAGIC: &[u8; 4] = b"BLC\x01";
+
+/// How deeply lists and code objects may be nested inside each other. Decoding recurses into each level, so this keeps crafted data from overflowing the stack; the compiler never comes close to it.
+const MAX_DEPTH: usize = 256;
+
+/// Reasons a bytecode file couldn't be loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The data doesn't start with [`MAGIC`], so either it isn't bytecode or it was written by a different version.
+    NotBytecode,
+    /// The data ended partway through.
+    Truncated,
+    /// The data contains something that can't be decoded, such as an unknown tag, invalid unicode, an operand that refers to a missing constant or variable, or values nested too deeply.
+    Malformed,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotBytecode => {
+                f.write_str("not a bytecode file for this version of the interpreter")
+            }
+            Self::Truncated => f.write_str("bytecode file ends unexpectedly"),
+            Self::Malformed => f.write_str("bytecode file is corrupted"),
+        }
+    }
+}
+
+/// Encodes compiled code along with its identifier table.
+pub fn to_bytes(code: &Code, idents: &IndexSet<Ident>) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    write_usize(&mut bytes, idents.len());
+    for ident in idents {
+        write_str(&mut bytes, ident);
+    }
+    write_code(&mut bytes, code);
+    bytes
+}
+
+/// Decodes code and its identifier table written by [`to_bytes`].
+pub fn from_bytes(bytes: &[u8]) -> Result<(Code, IndexSet<Ident>), DecodeError> {
+    let mut reader = Reader {
+        bytes: bytes.strip_prefix(MAGIC).ok_or(DecodeError::NotBytecode)?,
+        num_idents: 0,
+        depth: 0,
+    };
+    let num_idents = reader.count()?;
+    let mut idents = IndexSet::new();
+    for _ in 0..num_idents {
+        idents.insert(reader.string()?);
+    }
+    reader.num_idents = idents.len();
+    let code = reader.code()?;
+    if !reader.bytes.is_empty() {
+        return Err(DecodeError::Malformed);
+    }
+    Ok((code, idents))
+}
+
+fn write_usize(bytes: &mut Vec<u8>, mut n: usize) {
+    loop {
+        let low = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(low);
+            return;
+        }
+        bytes.push(low | 0x80);
+    }
+}
+
+fn write_str(bytes: &mut Vec<u8>, s: &str) {
+    write_usize(bytes, s.len());
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+fn write_op(bytes: &mut Vec<u8>, op: Op) {
+    let (tag, operands): (u8, &[usize]) = match op {
+        Op::GetConstant(i) => (0, &[i]),
+        Op::GetIdent(i) => (1, &[i]),
+        Op::Negate => (2, &[]),
+        Op::Drop => (3, &[]),
+        Op::Dup => (4, &[]),
+        Op::Assign(i) => (5, &[i]),
+        Op::Declare(i) => (6, &[i]),
+        Op::Shadow(i) => (7, &[i]),
+        Op::Closure(i) => (8, &[i]),
+        Op::Memoized(i) => (9, &[i]),
+        Op::DefaultArg(position, i) => (10, &[position, i]),
+        Op::CollectRest(n) => (11, &[n]),
+        Op::Call(n) => (12, &[n]),
+        Op::GetLocal(slot) => (14, &[slot]),
+        Op::AssignLocal(slot) => (15, &[slot]),
+        Op::CallBuiltin(intrinsic, n) => {
+            bytes.push(13);
+            write_str(bytes, intrinsic.name());
+            write_usize(bytes, n);
+            return;
+        }
+    };
+    bytes.push(tag);
+    for &operand in operands {
+        write_usize(bytes, operand);
+    }
+}
+
+fn write_value(bytes: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::None => bytes.push(0),
+        Value::Number(n) => {
+            bytes.push(1);
+            let n = match n {
+                TinyInt::Inline(x) => BigInt::from(*x),
+                TinyInt::Heap(h) => h.clone(),
+            };
+            let digits = n.to_signed_bytes_le();
+            write_usize(bytes, digits.len());
+            bytes.extend_from_slice(&digits);
+        }
+        Value::Str(s) => {
+            bytes.push(2);
+            write_str(bytes, s);
+        }
+        Value::List(list) => {
+            bytes.push(3);
+            write_usize(bytes, list.len());
+            for elem in list {
+                write_value(bytes, elem);
+            }
+        }
+        Value::Bytecode(code, num_params) => {
+            bytes.push(4);
+            write_usize(bytes, *num_params);
+            write_code(bytes, code);
+        }
+        // Builtins are stored by name, so that adding new ones doesn't invalidate existing files.
+        Value::Builtin(intrinsic) => {
+            bytes.push(5);
+            write_str(bytes, intrinsic.name());
+        }
+        Value::Lazy(_) => unreachable!("lazy sequences are only made at runtime"),
+    }
+}
+
+fn write_code(bytes: &mut Vec<u8>, code: &Code) {
+    write_usize(bytes, code.ops.len());
+    for &op in &code.ops {
+        write_op(bytes, op);
+    }
+    write_usize(bytes, code.constants.len());
+    for constant in &code.constants {
+        write_value(bytes, constant);
+    }
+    write_usize(bytes, code.num_optional);
+    bytes.push(code.variadic.into());
+    write_usize(bytes, code.free_idents.len());
+    for &ident in &code.free_idents {
+        write_usize(bytes, ident);
+    }
+    write_usize(bytes, code.locals.len());
+    for &ident in &code.locals {
+        write_usize(bytes, ident);
+    }
+}
+
+/// Reads values back out of encoded data, advancing past each one.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    /// The size of the identifier table, which every identifier operand must be within.
+    num_idents: usize,
+    /// How many lists and code objects are being read, each inside the last.
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (&first, rest) = self.bytes.split_first().ok_or(DecodeError::Truncated)?;
+        self.bytes = rest;
+        Ok(first)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        let mut n: usize = 0;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.byte()?;
+            let low = usize::from(byte & 0x7f);
+            n |= low
+                .checked_shl(shift)
+                .filter(|x| x >> shift == low)
+                .ok_or(DecodeError::Malformed)?;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(DecodeError::Malformed)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.usize()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::Malformed)
+    }
+
+    /// Reads a count of items that each take at least one byte, making sure there could be that many, so that a corrupted count can't make us allocate an enormous vector.
+    fn count(&mut self) -> Result<usize, DecodeError> {
+        let count = self.usize()?;
+        if count > self.bytes.len() {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(count)
+    }
+
+    /// Reads a builtin, which is stored by name.
+    fn intrinsic(&mut self) -> Result<Intrinsic, DecodeError> {
+        let name = self.string()?;
+        INTRINSIC_IDENTS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, intrinsic)| *intrinsic)
+            .ok_or(DecodeError::Malformed)
+    }
+
+    fn op(&mut self) -> Result<Op, DecodeError> {
+        Ok(match self.byte()? {
+            0 => Op::GetConstant(self.usize()?),
+            1 => Op::GetIdent(self.usize()?),
+            2 => Op::Negate,
+            3 => Op::Drop,
+            4 => Op::Dup,
+            5 => Op::Assign(self.usize()?),
+            6 => Op::Declare(self.usize()?),
+            7 => Op::Shadow(self.usize()?),
+            8 => Op::Closure(self.usize()?),
+            9 => Op::Memoized(self.usize()?),
+            10 => Op::DefaultArg(self.usize()?, self.usize()?),
+            11 => Op::CollectRest(self.usize()?),
+            12 => Op::Call(self.usize()?),
+            13 => Op::CallBuiltin(self.intrinsic()?, self.usize()?),
+            14 => Op::GetLocal(self.usize()?),
+            15 => Op::AssignLocal(self.usize()?),
+            _ => return Err(DecodeError::Malformed),
+        })
+    }
+
+    fn value(&mut self) -> Result<Value, DecodeError> {
+        Ok(match self.byte()? {
+            0 => Value::None,
+            1 => {
+                let len = self.usize()?;
+                let digits = self.take(len)?;
+                Value::Number(BigInt::from_signed_bytes_le(digits).into())
+            }
+            2 => Value::Str(self.string()?),
+            3 => {
+                let len = self.count()?;
+                let list = self.nested(|reader| (0..len).map(|_| reader.value()).collect())?;
+                Value::List(list)
+            }
+            4 => {
+                let num_params = self.usize()?;
+                Value::Bytecode(Rc::new(self.nested(Self::code)?), num_params)
+            }
+            5 => Value::Builtin(self.intrinsic()?),
+            _ => return Err(DecodeError::Malformed),
+        })
+    }
+
+    fn code(&mut self) -> Result<Code, DecodeError> {
+        let num_ops = self.count()?;
+        let ops = (0..num_ops).map(|_| self.op()).collect::<Result<_, _>>()?;
+        let num_constants = self.count()?;
+        let constants = (0..num_constants)
+            .map(|_| self.value())
+            .collect::<Result<_, _>>()?;
+        let num_optional = self.usize()?;
+        let variadic = match self.byte()? {
+            0 => false,
+            1 => true,
+            _ => return Err(DecodeError::Malformed),
+        };
+        let num_free = self.count()?;
+        let free_idents = (0..num_free)
+            .map(|_| self.usize())
+            .collect::<Result<_, _>>()?;
+        let num_locals = self.count()?;
+        let locals = (0..num_locals)
+            .map(|_| self.usize())
+            .collect::<Result<_, _>>()?;
+        let code = Code {
+            ops,
+            constants,
+            num_optional,
+            variadic,
+            free_idents,
+            locals,
+            ..Code::default()
+        };
+        if !self.operands_valid(&code) {
+            return Err(DecodeError::Malformed);
+        }
+        Ok(code)
+    }
+
+    /// Reads something holding further values, one level deeper than the current one.
+    fn nested<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<T, DecodeError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(DecodeError::Malformed);
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Checks that every constant, identifier, and local slot the code refers to exists, so that the interpreter never has to deal with ones that don't.
+    fn operands_valid(&self, code: &Code) -> bool {
+        let ident_valid = |ident: &usize| *ident < self.num_idents;
+        let ops_valid = code.ops.iter().all(|op| match *op {
+            Op::GetConstant(i) | Op::Closure(i) | Op::Memoized(i) | Op::DefaultArg(_, i) => {
+                i < code.constants.len()
+            }
+            Op::GetIdent(ident) | Op::Assign(ident) => ident_valid(&ident),
+            Op::Declare(slot) | Op::Shadow(slot) | Op::GetLocal(slot) | Op::AssignLocal(slot) => {
+                slot < code.locals.len()
+            }
+            Op::Negate | Op::Drop | Op::Dup | Op::CollectRest(_) | Op::Call(_) => true,
+            Op::CallBuiltin(_, _) => true,
+        });
+        ops_valid && code.free_idents.iter().all(ident_valid) && code.locals.iter().all(ident_valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::{compile_with, Return};
+    use crate::interpret::Executor;
+    use crate::parse::parse;
+
+    #[test]
+    fn round_trip() {
+        let source = "
+            big := 123456789012345678901234567890
+            f := (a b = -4 rest...) => push(rest add(a b))
+            g := memoize((n) => mul(n 2))
+            push(push(f(1 2 3) g(big)) \"text\")
+        ";
+        let (code, idents) = compile_with(parse(source).unwrap(), Return::Keep);
+        let bytes = to_bytes(&code, &idents);
+        let (loaded, loaded_idents) = from_bytes(&bytes).unwrap();
+        assert_eq!(loaded_idents, idents);
+        assert_eq!(to_bytes(&loaded, &loaded_idents), bytes);
+        let mut exec = Executor::from_code(loaded, loaded_idents);
+        exec.initialize_builtins();
+        exec.run().unwrap().unwrap();
+        assert_eq!(
+            exec.take_result().unwrap().to_string(),
+            r#"[3, 3, 246913578024691357802469135780, "text"]"#
+        );
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let (code, idents) = compile_with(parse("print(\"hi\")").unwrap(), Return::Discard);
+        let bytes = to_bytes(&code, &idents);
+        assert_eq!(
+            from_bytes(b"print(1)").unwrap_err(),
+            DecodeError::NotBytecode
+        );
+        assert_eq!(
+            from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            DecodeError::Truncated
+        );
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert_eq!(from_bytes(&extra).unwrap_err(), DecodeError::Malformed);
+        let mut huge_count = MAGIC.to_vec();
+        huge_count.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert_eq!(from_bytes(&huge_count).unwrap_err(), DecodeError::Truncated);
+    }
+
+    /// Encodes a main code object with the given ops and constants, no variables, and an empty identifier table.
+    fn encoded(ops: Vec<Op>, constants: Vec<Value>) -> Vec<u8> {
+        let code = Code {
+            ops,
+            constants,
+            ..Code::default()
+        };
+        to_bytes(&code, &IndexSet::new())
+    }
+
+    #[test]
+    fn rejects_missing_operands() {
+        let list = Value::List(vec![]);
+        assert!(from_bytes(&encoded(vec![Op::GetConstant(0)], vec![list.clone()])).is_ok());
+        for op in [
+            Op::GetConstant(1),
+            Op::Closure(1),
+            Op::GetIdent(0),
+            Op::Declare(0),
+            Op::GetLocal(1 << 40),
+        ] {
+            assert_eq!(
+                from_bytes(&encoded(vec![op], vec![list.clone()])).unwrap_err(),
+                DecodeError::Malformed
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let nest = |depth: usize| {
+            let mut value = Value::None;
+            for _ in 0..depth {
+                value = Value::List(vec![value]);
+            }
+            encoded(vec![], vec![value])
+        };
+        assert!(from_bytes(&nest(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            from_bytes(&nest(MAX_DEPTH + 1)).unwrap_err(),
+            DecodeError::Malformed
+        );
+        // Crafted data far deeper than the limit is refused without recursing into all of it.
+        let mut bytes = encoded(vec![], vec![Value::None]);
+        let at = bytes.len() - 5;
+        let deep = [3, 1].repeat(1_000_000);
+        bytes.splice(at..at, deep);
+        assert_eq!(from_bytes(&bytes).unwrap_err(), DecodeError::Malformed);
+    }
+}
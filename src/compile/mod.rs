@@ -1,11 +1,16 @@
 //! Compiles an AST to bytecode.
 
+mod encode;
 mod tiny_int;
 
 use crate::parse::{Expr, Ident};
 use indexmap::IndexSet;
 //use num_traits::identities::Zero;
+pub use encode::{from_bytes, to_bytes, MAGIC};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
 pub use tiny_int::TinyInt;
 
 /// Bytecode operations.
@@ -15,19 +20,61 @@ pub enum Op {
     GetConstant(usize),
     /// Retrieve the value of a variable from the nearest scope it's defined, and push it to the stack. If the variable has not been defined, a [`VariableNotFound`](crate::interpret::ScriptError::VariableNotFound) error is thrown.
     GetIdent(usize),
+    /// Pop a number from the stack and push its negation. If the value isn't a number, an [`ArgumentType`](crate::interpret::ScriptError::ArgumentType) error is thrown.
+    Negate,
     /// Pop a value from the stack and discard it.
     Drop,
     /// Duplicate the value at the top of the stack.
     Dup,
     /// Pop a value from the stack and assign it to a variable from the nearest scope. If the variable has not been defined, a [`VariableNotFound`](crate::interpret::ScriptError::VariableNotFound) error is thrown.
     Assign(usize),
-    /// Pop a value from the stack and declare a variable in the current scope initialized with said value. If the variable has already been declared in the current scope, a [`VariableRedeclared`](crate::interpret::ScriptError::VariableRedeclared) error is thrown.
+    /// Pop a value from the stack and declare the variable in the given [local slot](Code::locals) initialized with said value. If the variable has already been declared in the current scope, a [`VariableRedeclared`](crate::interpret::ScriptError::VariableRedeclared) error is thrown.
     Declare(usize),
+    /// Pop a value from the stack and declare the variable in the given [local slot](Code::locals) initialized with said value, replacing the variable if it has already been declared in the current scope. Variables of the same name in parent scopes are left untouched.
+    Shadow(usize),
+    /// Like [`GetIdent`](Op::GetIdent), but for a variable the current code declares, found by its [local slot](Code::locals). Until it is declared, the variable is looked up by name like any other.
+    GetLocal(usize),
+    /// Like [`Assign`](Op::Assign), but for a variable the current code declares, found by its [local slot](Code::locals).
+    AssignLocal(usize),
+    /// Push a copy of the bytecode constant at the given index, with every one of its [free variables](Code::free_idents) that is currently defined captured by reference.
+    Closure(usize),
+    /// Call the bytecode constant at the given index with the arguments passed to the current function, reusing the result from the [cache](Code::memo) if it has been called with equal arguments before.
+    Memoized(usize),
+    /// If fewer arguments than the given position were passed to the current function, call the zero-argument bytecode constant at the given index to push a default in place of the missing argument.
+    DefaultArg(usize, usize),
+    /// Collect every argument passed to the current function past the given number into a list, and push that list. Used at the start of a variadic function to gather its rest parameter.
+    CollectRest(usize),
     /// Pop a bytecode object from the stack and execute it. Additionally, some number of values are popped from the parent stack and pushed onto the child stack. This code may leave a single value on the stack as its return value.
     Call(usize),
+    /// Calls a builtin directly with the given number of arguments, without looking it up. It is emitted in place of [`Call`](Op::Call) for calls to a builtin by its name when nothing in the program binds that name to something else.
+    CallBuiltin(Intrinsic, usize),
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Op {
+    /// The name of this kind of op, without its operands.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::GetConstant(_) => "GetConstant",
+            Self::GetIdent(_) => "GetIdent",
+            Self::Negate => "Negate",
+            Self::Drop => "Drop",
+            Self::Dup => "Dup",
+            Self::Assign(_) => "Assign",
+            Self::Declare(_) => "Declare",
+            Self::Shadow(_) => "Shadow",
+            Self::GetLocal(_) => "GetLocal",
+            Self::AssignLocal(_) => "AssignLocal",
+            Self::Closure(_) => "Closure",
+            Self::Memoized(_) => "Memoized",
+            Self::DefaultArg(..) => "DefaultArg",
+            Self::CollectRest(_) => "CollectRest",
+            Self::Call(_) => "Call",
+            Self::CallBuiltin(..) => "CallBuiltin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Intrinsic {
     Print,
     While,
@@ -45,9 +92,89 @@ pub enum Intrinsic {
     Filter,
     Zip,
     At,
+    Args,
+    Env,
+    Exit,
+    Clock,
+    Random,
+    Seed,
+    Shuffle,
+    Sample,
+    Unique,
+    GroupBy,
+    Partition,
+    ZipWith,
+    ZipN,
+    Insert,
+    Remove,
+    SetAt,
+    UpdateAt,
+    Replicate,
+    Seq,
+    Memoize,
+    Breakpoint,
+    IsNone,
+    IsNumber,
+    IsString,
+    IsList,
+    IsCallable,
+    Eq,
+    Contains,
+    DoWhile,
+    Loop,
+    Break,
+    ToRadix,
+    MinBy,
+    MaxBy,
+    Chunks,
+    Windows,
+    Builtins,
+    Help,
+    Cons,
+    FoldLeft,
+    FoldRight,
+    Iterate,
+    Sum,
+    Product,
+    Clamp,
+    Sign,
+    TakeWhile,
+    DropWhile,
+    Default,
+    DefaultWith,
+    Match,
+    Write,
+    Flush,
+    Upper,
+    Lower,
+    Trim,
+    StartsWith,
+    EndsWith,
+    Replace,
+    Substring,
+    Find,
+    Range,
+    Collect,
+    Each,
+    Not,
+    Chars,
+    Ord,
+    Chr,
+    DeepMap,
+    AssertEq,
+    Once,
+    Union,
+    Intersection,
+    Difference,
+    Transpose,
+    WithCleanup,
+    ToNumber,
+    ToBytes,
+    FromBytes,
+    SortByKey,
 }
 
-pub const INTRINSIC_IDENTS: [(&str, Intrinsic); 16] = [
+pub const INTRINSIC_IDENTS: [(&str, Intrinsic); 96] = [
     ("print", Intrinsic::Print),
     ("while", Intrinsic::While),
     ("add", Intrinsic::Add),
@@ -64,78 +191,486 @@ pub const INTRINSIC_IDENTS: [(&str, Intrinsic); 16] = [
     ("filter", Intrinsic::Filter),
     ("zip", Intrinsic::Zip),
     ("at", Intrinsic::At),
+    ("args", Intrinsic::Args),
+    ("env", Intrinsic::Env),
+    ("exit", Intrinsic::Exit),
+    ("clock", Intrinsic::Clock),
+    ("random", Intrinsic::Random),
+    ("seed", Intrinsic::Seed),
+    ("shuffle", Intrinsic::Shuffle),
+    ("sample", Intrinsic::Sample),
+    ("unique", Intrinsic::Unique),
+    ("group_by", Intrinsic::GroupBy),
+    ("partition", Intrinsic::Partition),
+    ("zip_with", Intrinsic::ZipWith),
+    ("zip_n", Intrinsic::ZipN),
+    ("insert", Intrinsic::Insert),
+    ("remove", Intrinsic::Remove),
+    ("set_at", Intrinsic::SetAt),
+    ("update_at", Intrinsic::UpdateAt),
+    ("replicate", Intrinsic::Replicate),
+    ("seq", Intrinsic::Seq),
+    ("memoize", Intrinsic::Memoize),
+    ("breakpoint", Intrinsic::Breakpoint),
+    ("is_none", Intrinsic::IsNone),
+    ("is_number", Intrinsic::IsNumber),
+    ("is_string", Intrinsic::IsString),
+    ("is_list", Intrinsic::IsList),
+    ("is_callable", Intrinsic::IsCallable),
+    ("eq", Intrinsic::Eq),
+    ("contains", Intrinsic::Contains),
+    ("do_while", Intrinsic::DoWhile),
+    ("loop", Intrinsic::Loop),
+    ("break", Intrinsic::Break),
+    ("to_radix", Intrinsic::ToRadix),
+    ("min_by", Intrinsic::MinBy),
+    ("max_by", Intrinsic::MaxBy),
+    ("chunks", Intrinsic::Chunks),
+    ("windows", Intrinsic::Windows),
+    ("builtins", Intrinsic::Builtins),
+    ("help", Intrinsic::Help),
+    ("cons", Intrinsic::Cons),
+    ("fold_left", Intrinsic::FoldLeft),
+    ("fold_right", Intrinsic::FoldRight),
+    ("iterate", Intrinsic::Iterate),
+    ("sum", Intrinsic::Sum),
+    ("product", Intrinsic::Product),
+    ("clamp", Intrinsic::Clamp),
+    ("sign", Intrinsic::Sign),
+    ("take_while", Intrinsic::TakeWhile),
+    ("drop_while", Intrinsic::DropWhile),
+    ("default", Intrinsic::Default),
+    ("default_with", Intrinsic::DefaultWith),
+    ("match", Intrinsic::Match),
+    ("write", Intrinsic::Write),
+    ("flush", Intrinsic::Flush),
+    ("upper", Intrinsic::Upper),
+    ("lower", Intrinsic::Lower),
+    ("trim", Intrinsic::Trim),
+    ("starts_with", Intrinsic::StartsWith),
+    ("ends_with", Intrinsic::EndsWith),
+    ("replace", Intrinsic::Replace),
+    ("substring", Intrinsic::Substring),
+    ("find", Intrinsic::Find),
+    ("range", Intrinsic::Range),
+    ("collect", Intrinsic::Collect),
+    ("each", Intrinsic::Each),
+    ("not", Intrinsic::Not),
+    ("chars", Intrinsic::Chars),
+    ("ord", Intrinsic::Ord),
+    ("chr", Intrinsic::Chr),
+    ("deep_map", Intrinsic::DeepMap),
+    ("assert_eq", Intrinsic::AssertEq),
+    ("once", Intrinsic::Once),
+    ("union", Intrinsic::Union),
+    ("intersection", Intrinsic::Intersection),
+    ("difference", Intrinsic::Difference),
+    ("transpose", Intrinsic::Transpose),
+    ("with_cleanup", Intrinsic::WithCleanup),
+    ("to_number", Intrinsic::ToNumber),
+    ("to_bytes", Intrinsic::ToBytes),
+    ("from_bytes", Intrinsic::FromBytes),
+    ("sort_by_key", Intrinsic::SortByKey),
 ];
 
 impl Intrinsic {
     pub const fn num_params(self) -> usize {
         match self {
-            Self::List => 0,
-            Self::Len | Self::Last | Self::Print => 1,
+            Self::Args
+            | Self::Breakpoint
+            | Self::Builtins
+            | Self::Clock
+            | Self::Flush
+            | Self::List => 0,
+            Self::Break
+            | Self::Chars
+            | Self::Chr
+            | Self::Collect
+            | Self::Env
+            | Self::Exit
+            | Self::FromBytes
+            | Self::Help
+            | Self::IsCallable
+            | Self::IsList
+            | Self::IsNone
+            | Self::IsNumber
+            | Self::IsString
+            | Self::Len
+            | Self::Last
+            | Self::Loop
+            | Self::Lower
+            | Self::Memoize
+            | Self::Not
+            | Self::Once
+            | Self::Ord
+            | Self::Print
+            | Self::Product
+            | Self::Random
+            | Self::Seed
+            | Self::Seq
+            | Self::Shuffle
+            | Self::Sign
+            | Self::Sum
+            | Self::ToBytes
+            | Self::ToNumber
+            | Self::Transpose
+            | Self::Trim
+            | Self::Unique
+            | Self::Upper
+            | Self::Write
+            | Self::ZipN => 1,
             Self::Add
+            | Self::AssertEq
             | Self::At
+            | Self::Chunks
+            | Self::Cons
+            | Self::Contains
+            | Self::DeepMap
+            | Self::Default
+            | Self::DefaultWith
+            | Self::Difference
             | Self::Div
+            | Self::DoWhile
+            | Self::DropWhile
+            | Self::Each
+            | Self::EndsWith
+            | Self::Eq
             | Self::Filter
+            | Self::Find
             | Self::Fold
+            | Self::GroupBy
+            | Self::Intersection
             | Self::Map
+            | Self::Match
+            | Self::MaxBy
+            | Self::MinBy
             | Self::Mod
             | Self::Mul
+            | Self::Partition
             | Self::Push
+            | Self::Range
+            | Self::Remove
+            | Self::Replicate
+            | Self::Sample
+            | Self::SortByKey
+            | Self::StartsWith
             | Self::Sub
+            | Self::TakeWhile
+            | Self::ToRadix
+            | Self::Union
             | Self::While
+            | Self::Windows
+            | Self::WithCleanup
             | Self::Zip => 2,
+            Self::Clamp
+            | Self::FoldLeft
+            | Self::FoldRight
+            | Self::Insert
+            | Self::Iterate
+            | Self::Replace
+            | Self::SetAt
+            | Self::Substring
+            | Self::UpdateAt
+            | Self::ZipWith => 3,
         }
     }
+
+    /// A one sentence description of what this builtin does, as shown by the `help` builtin.
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Print => "Prints a value followed by a newline, and flushes the output.",
+            Self::While => "Calls the body for as long as calling the condition returns a truthy value.",
+            Self::Add => "Adds two numbers.",
+            Self::Sub => "Subtracts the second number from the first.",
+            Self::Mul => "Multiplies two numbers.",
+            Self::Div => "Divides the first number by the second, rounding towards zero, or returns `None` when dividing by zero.",
+            Self::Mod => "Returns the remainder of dividing the first number by the second, with the sign of the second, or `None` when dividing by zero.",
+            Self::List => "Returns an empty list.",
+            Self::Last => "Returns the last element of a list.",
+            Self::Push => "Returns the list with the value added to the end.",
+            Self::Len => "Returns the number of elements in a list or characters in a string.",
+            Self::Map => "Returns a list of the results of calling the function on each element of the list.",
+            Self::Fold => "Like `fold_right`, but starting from the last element of the list instead of an initial value.",
+            Self::Filter => "Returns the elements of the list for which the function returns a truthy value.",
+            Self::Zip => "Pairs up the corresponding elements of two lists.",
+            Self::At => "Returns the element of the list or character of the string at the index, counting from the end if it is negative.",
+            Self::Args => "Returns the command line arguments passed to the script.",
+            Self::Env => "Returns the value of an environment variable, or `None` if it is unset.",
+            Self::Exit => "Halts the script with the given exit code.",
+            Self::Clock => "Returns the number of milliseconds since the script started.",
+            Self::Random => "Returns a random number from zero up to but not including the argument.",
+            Self::Seed => "Reseeds the random number generator.",
+            Self::Shuffle => "Returns the list in a random order.",
+            Self::Sample => "Returns the given number of elements picked at random from the list.",
+            Self::Unique => "Returns the list without elements equal to earlier ones.",
+            Self::GroupBy => "Groups the elements of the list into `[key, elements]` pairs by the key the function computes.",
+            Self::Partition => "Splits the list into the elements for which the function returns a truthy value and the rest.",
+            Self::ZipWith => "Combines the corresponding elements of two lists with a two argument function.",
+            Self::ZipN => "Zips a list of lists into lists of corresponding elements.",
+            Self::Insert => "Returns the list with the value inserted at the index.",
+            Self::Remove => "Returns the list without the element at the index.",
+            Self::SetAt => "Returns the list with the element at the index replaced by the value.",
+            Self::UpdateAt => "Returns the list with the element at the index replaced by the result of calling the function on it.",
+            Self::Replicate => "Returns a list of the given number of copies of the value.",
+            Self::Seq => "Calls each function in a list in order and returns the result of the last.",
+            Self::Memoize => "Wraps a function so that calls with equal arguments reuse the earlier result.",
+            Self::Breakpoint => "Prints the variables in scope and the stack for debugging.",
+            Self::IsNone => "Returns whether the value is `None`.",
+            Self::IsNumber => "Returns whether the value is a number.",
+            Self::IsString => "Returns whether the value is a string.",
+            Self::IsList => "Returns whether the value is a list.",
+            Self::IsCallable => "Returns whether the value is a function or builtin.",
+            Self::Eq => "Returns whether two values are equal.",
+            Self::Contains => "Returns whether any element of the list is equal to the value, or whether the string contains the other string.",
+            Self::DoWhile => "Calls the body, then keeps calling it for as long as calling the condition returns a truthy value.",
+            Self::Loop => "Calls the body over and over until it calls `break`, and returns the value given to `break`.",
+            Self::Break => "Stops the innermost `loop`, making it return the value.",
+            Self::ToRadix => "Writes a number as a string in a base from 2 to 36.",
+            Self::MinBy => "Returns the first element of the list with the smallest key computed by the function.",
+            Self::MaxBy => "Returns the first element of the list with the largest key computed by the function.",
+            Self::Chunks => "Splits the list into consecutive sublists of the given size.",
+            Self::Windows => "Returns every overlapping run of consecutive elements of the given size.",
+            Self::Builtins => "Returns the names of every builtin function.",
+            Self::Help => "Describes the builtin function with the given name.",
+            Self::Cons => "Returns the list with the value added to the front.",
+            Self::FoldLeft => "Combines an initial value with each element of the list from the front, as in `f(f(init a) b)`.",
+            Self::Iterate => "Returns a list of the given length starting with the seed, where each element is the function applied to the one before it.",
+            Self::Sum => "Adds up every number in the list.",
+            Self::Product => "Multiplies together every number in the list.",
+            Self::Clamp => "Returns the number bounded to the range from the low to the high number.",
+            Self::Sign => "Returns `-1`, `0`, or `1` depending on whether the number is negative, zero, or positive.",
+            Self::TakeWhile => "Returns the elements at the front of the list up to the first for which the function returns a falsy value.",
+            Self::DropWhile => "Returns the list from the first element for which the function returns a falsy value onwards.",
+            Self::Default => "Returns the fallback if the value is `None`, and the value otherwise.",
+            Self::DefaultWith => "Returns the result of calling the function if the value is `None`, and the value otherwise.",
+            Self::Match => "Calls the handler of the first `[predicate handler]` pair whose predicate returns a truthy value when called with the value, passing it the value too.",
+            Self::Write => "Prints a value without a newline. The output may not appear until it is flushed.",
+            Self::Flush => "Makes sure everything printed so far has been written out.",
+            Self::Upper => "Returns the string in uppercase.",
+            Self::Lower => "Returns the string in lowercase.",
+            Self::Trim => "Returns the string without whitespace at the start or end.",
+            Self::StartsWith => "Returns whether the first string starts with the second.",
+            Self::EndsWith => "Returns whether the first string ends with the second.",
+            Self::Replace => "Returns the first string with every occurrence of the second replaced by the third.",
+            Self::Substring => "Returns the characters of the string between two indices, which may be negative.",
+            Self::Find => "Returns the index of the first character where the second string appears in the first, or `None` if it doesn't.",
+            Self::Range => "Returns a lazy sequence of the numbers from the start up to but not including the end.",
+            Self::Collect => "Returns a list of the elements of a lazy sequence.",
+            Self::Each => "Calls the function on each element of a list or lazy sequence, returning `None`.",
+            Self::Not => "Returns whether the value is falsy, so `1` for `0`, `None`, and empty strings and lists.",
+            Self::Chars => "Returns a list of the characters of the string, each as a string of its own.",
+            Self::Ord => "Returns the unicode code point of a single character string.",
+            Self::Chr => "Returns a single character string for the unicode code point.",
+            Self::DeepMap => "Returns a list of the same shape with the function called on every value inside it that isn't a list, however deeply nested.",
+            Self::AssertEq => "Raises an error showing both values if they aren't equal, and otherwise returns `None`.",
+            Self::Once => "Wraps a function of no arguments so that it only runs the first time it's called, with later calls returning the same result.",
+            Self::Union => "Returns the elements that are in either list, without duplicates.",
+            Self::Intersection => "Returns the elements of the first list that are also in the second, without duplicates.",
+            Self::Difference => "Returns the elements of the first list that aren't in the second, without duplicates.",
+            Self::Transpose => "Turns a list of equally long rows into a list of columns.",
+            Self::WithCleanup => "Runs a function of no arguments, then another to clean up after it, even if the first raised an error. Returns the result of the first.",
+            Self::ToNumber => "Reads an integer from a string written like a number literal, or returns None if it isn't one.",
+            Self::ToBytes => "Returns the bytes of a string's UTF-8 encoding as a list of numbers.",
+            Self::FromBytes => "Builds a string from a list of the bytes of its UTF-8 encoding.",
+            Self::SortByKey => "Sorts a list by the number a function returns for each element, keeping elements with equal keys in order.",
+            Self::FoldRight => "Combines each element of the list with an initial value from the back, as in `f(a f(b init))`.",
+        }
+    }
+
+    /// The name this builtin is bound to in user code.
+    pub fn name(self) -> &'static str {
+        INTRINSIC_IDENTS
+            .iter()
+            .find(|(_, intrinsic)| *intrinsic == self)
+            .map(|(name, _)| *name)
+            .expect("every intrinsic has a name")
+    }
 }
 
 /// A value which can be created and manipulated by user code.
 #[derive(Debug, Clone)]
 pub enum Value {
-    /// A null value that is returned when there is no other possible value. The canonical representation of this value is the empty block `{}`.
+    /// A null value that is returned when there is no other possible value, written `none`. The empty block `{}` also evaluates to it.
     None,
     /// An integer value.
     Number(TinyInt),
+    /// A string of text. There is no separate character type, so builtins that take or return a single character, like `chars` and `ord`, use a string of exactly one character instead.
+    Str(String),
     /// A list of values.
     List(Vec<Value>),
-    /// An executable bytecode value, as well as the number of arguments it requires (if any).
-    Bytecode(Code, usize),
+    /// An executable bytecode value, as well as the number of arguments it requires (if any). If the code has [optional parameters](Code::num_optional) or is [`variadic`](Code::variadic), this is only the minimum number of arguments.
+    Bytecode(Rc<Code>, usize),
     /// An intrinsic function whose behavior is handled by the compiler/interpreter.
     Builtin(Intrinsic),
+    /// A sequence whose elements are only worked out as they are read, so they never all have to be in memory at once.
+    Lazy(Rc<Lazy>),
 }
 
+/// Describes how to produce the elements of a lazy sequence. It holds no position, so each reading of the sequence starts over from the beginning, running any functions in it again.
+#[derive(Debug, Clone)]
+pub enum Lazy {
+    /// The numbers from the start up to but not including the end.
+    Range(TinyInt, TinyInt),
+    /// The result of calling a one argument function on each element of a sequence.
+    Map(Rc<Code>, Rc<Lazy>),
+    /// The elements of a sequence for which a one argument function returns something truthy.
+    Filter(Rc<Code>, Rc<Lazy>),
+}
+
+/// A hashable copy of a value that can be compared by value, such as for caching.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    None,
+    Number(TinyInt),
+    Str(String),
+    List(Vec<Key>),
+    Builtin(Intrinsic),
+}
+
+/// Cached results of a memoized function, keyed by the arguments it was called with.
+pub type Memo = HashMap<Vec<Key>, Value>;
+
 impl Value {
+    /// Returns the key for this value, or `None` if it can't be compared by value because it is or contains bytecode or a lazy sequence.
+    pub fn to_key(&self) -> Option<Key> {
+        Some(match self {
+            Self::None => Key::None,
+            Self::Number(n) => Key::Number(n.clone()),
+            Self::Str(s) => Key::Str(s.clone()),
+            Self::List(list) => Key::List(list.iter().map(Self::to_key).collect::<Option<_>>()?),
+            Self::Builtin(intrinsic) => Key::Builtin(*intrinsic),
+            Self::Bytecode(..) | Self::Lazy(_) => return None,
+        })
+    }
+
     pub fn truthiness(&self) -> bool {
         match self {
             Self::None => false,
             Self::Number(n) => !n.is_zero(),
+            Self::Str(s) => !s.is_empty(),
             Self::List(list) => !list.is_empty(),
-            Self::Bytecode(..) | Self::Builtin(_) => true,
+            // Whether a lazy sequence is empty can't be known without running it.
+            Self::Bytecode(..) | Self::Builtin(_) | Self::Lazy(_) => true,
         }
     }
 }
 
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// Values are compared structurally: lists are equal if their elements are, and numbers and strings are equal if their contents are. Builtins are equal if they're the same builtin, Bytecode is equal only to copies of the same function, compared by [identity](Code::identity), which includes identical lambdas that capture nothing, while lazy sequences are never equal to anything, not even themselves, since they can't be cheaply compared.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::None, Self::None) => true,
+            (Self::Number(x), Self::Number(y)) => x == y,
+            (Self::Str(x), Self::Str(y)) => x == y,
+            (Self::Builtin(x), Self::Builtin(y)) => x == y,
+            (Self::List(x), Self::List(y)) => x == y,
+            (Self::Bytecode(x, _), Self::Bytecode(y, _)) => Rc::ptr_eq(&x.identity, &y.identity),
+            _ => false,
+        }
+    }
+}
+
+/// Since there is no boolean type, `true` and `false` are represented by the numbers `1` and `0`.
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Number(TinyInt::Inline(b as isize))
+    }
+}
+
+/// How deeply lists are printed before the rest of their contents are replaced with `...`.
+pub const DISPLAY_DEPTH_LIMIT: usize = 64;
+
+impl Value {
+    /// Formats the value like [`Display`](fmt::Display), but writes `...` in place of any list nested more than `max_depth` lists deep, and only the first `max_len` elements of each list followed by how many were left out. This works through an explicit stack rather than recursion, so arbitrarily deep lists can't overflow the call stack.
+    pub fn fmt_limited(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        max_depth: usize,
+        max_len: usize,
+    ) -> fmt::Result {
         use fmt::Write;
-        match self {
-            Self::None => f.write_str("None"),
-            Self::Number(n) => write!(f, "{}", n),
-            Self::List(list) => {
-                f.write_char('[')?;
-                for (i, elem) in list.iter().enumerate() {
-                    if i != 0 {
-                        f.write_str(", ")?;
+        enum Work<'a> {
+            Value(&'a Value, usize),
+            Separator,
+            More(usize),
+            Close,
+        }
+        let mut work = vec![Work::Value(self, 0)];
+        while let Some(item) = work.pop() {
+            let (value, depth) = match item {
+                Work::Separator => {
+                    f.write_str(", ")?;
+                    continue;
+                }
+                Work::More(n) => {
+                    write!(f, "... ({} more)", n)?;
+                    continue;
+                }
+                Work::Close => {
+                    f.write_char(']')?;
+                    continue;
+                }
+                Work::Value(value, depth) => (value, depth),
+            };
+            match value {
+                Self::None => f.write_str("None")?,
+                Self::Number(n) => write!(f, "{}", n)?,
+                // Strings are quoted inside lists so that their boundaries stay visible.
+                Self::Str(s) if depth > 0 => write!(f, "{:?}", s)?,
+                Self::Str(s) => f.write_str(s)?,
+                Self::List(_) if depth >= max_depth => f.write_str("...")?,
+                Self::List(list) => {
+                    f.write_char('[')?;
+                    // Pushed in reverse, since the last item pushed is written first.
+                    work.push(Work::Close);
+                    if list.len() > max_len {
+                        work.push(Work::More(list.len() - max_len));
+                        if max_len > 0 {
+                            work.push(Work::Separator);
+                        }
+                    }
+                    for (i, elem) in list.iter().take(max_len).enumerate().rev() {
+                        work.push(Work::Value(elem, depth + 1));
+                        if i != 0 {
+                            work.push(Work::Separator);
+                        }
                     }
-                    write!(f, "{}", elem)?;
                 }
-                f.write_char(']')
-            }
-            Self::Bytecode(_, num_params) => write!(f, "<{} argument function>", num_params),
-            Self::Builtin(intrinsic) => {
-                let mut name = format!("{:?}", intrinsic);
-                name.make_ascii_lowercase();
-                write!(f, "<builtin function {}>", name)
+                Self::Bytecode(code, num_params) if code.variadic => {
+                    write!(f, "<{}+ argument function>", num_params)?
+                }
+                Self::Bytecode(code, num_params) if code.num_optional > 0 => {
+                    let max_params = num_params + code.num_optional;
+                    write!(f, "<{}-{} argument function>", num_params, max_params)?
+                }
+                Self::Bytecode(_, num_params) => write!(f, "<{} argument function>", num_params)?,
+                Self::Builtin(intrinsic) => write!(f, "<builtin function {}>", intrinsic.name())?,
+                Self::Lazy(_) => f.write_str("<lazy sequence>")?,
             }
         }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_limited(f, DISPLAY_DEPTH_LIMIT, usize::MAX)
+    }
+}
+
+/// Displays a value with only the first few elements of each list, as returned by [`Value::truncated`].
+pub struct Truncated<'a>(&'a Value, usize);
+
+impl Value {
+    /// Returns something that displays the value with at most `max_len` elements of each list, for printing values that may be huge.
+    pub fn truncated(&self, max_len: usize) -> Truncated<'_> {
+        Truncated(self, max_len)
+    }
+}
+
+impl fmt::Display for Truncated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_limited(f, DISPLAY_DEPTH_LIMIT, self.1)
     }
 }
 
@@ -145,11 +680,131 @@ pub struct Code {
     pub ops: Vec<Op>,
     //pub idents: Vec<Ident>,
     pub constants: Vec<Value>,
+    /// How many arguments past the required ones may be left out, to be replaced with default values.
+    pub num_optional: usize,
+    /// Whether this code accepts any number of arguments past its parameters, collecting the extras into a list.
+    pub variadic: bool,
+    /// Variables used by this code that are not its own parameters, which a closure made from it will capture.
+    pub free_idents: Vec<usize>,
+    /// The identifier of each variable this code declares, by its slot. Each call gets its own slots for these, so that they can be found without searching by name.
+    pub locals: Vec<usize>,
+    /// The variables captured when this closure was created. They are looked up after the variables declared in the current scope, but before those of the caller. Variables that weren't defined yet when the closure was created, such as the closure itself in a recursive function, are found through the caller as usual.
+    ///
+    /// A closure that is stored in one of its own captured variables keeps itself alive, and will be leaked.
+    pub captures: Vec<(usize, Rc<RefCell<Value>>)>,
+    /// The results of previous calls by their arguments, for code created by the `memoize` builtin. It is shared between every copy of the function.
+    pub memo: Option<Rc<RefCell<Memo>>>,
+    /// Tells functions apart, since comparing their code would be slow and wouldn't account for their captures. It is shared between every copy of the function, while each new closure gets its own. Identical lambdas that capture nothing are compiled to a single constant, so they share it too.
+    pub identity: Rc<()>,
+}
+
+impl Code {
+    /// Adds every variable this code reads or assigns, including from within nested code, to the set.
+    fn referenced_idents(&self, idents: &mut IndexSet<usize>) {
+        for op in &self.ops {
+            match *op {
+                Op::GetIdent(ident) | Op::Assign(ident) => {
+                    idents.insert(ident);
+                }
+                // A local read before it's declared is looked up by name, so it may be captured too.
+                Op::GetLocal(slot) | Op::AssignLocal(slot) => {
+                    idents.insert(self.locals[slot]);
+                }
+                _ => {}
+            }
+        }
+        for constant in &self.constants {
+            if let Value::Bytecode(code, _) = constant {
+                code.referenced_idents(idents);
+            }
+        }
+    }
+
+    /// Returns whether this code is identical to the other, so that the compiler can store it in the constant pool only once. This walks the code in full, which is fine at compile time. Closures and memoized functions only exist at runtime and are never considered identical.
+    fn same_code(&self, other: &Self) -> bool {
+        self.ops == other.ops
+            && self.num_optional == other.num_optional
+            && self.variadic == other.variadic
+            && self.free_idents == other.free_idents
+            && self.locals == other.locals
+            && self.captures.is_empty()
+            && other.captures.is_empty()
+            && self.memo.is_none()
+            && other.memo.is_none()
+            && self.constants.len() == other.constants.len()
+            && self
+                .constants
+                .iter()
+                .zip(&other.constants)
+                .all(|(x, y)| same_constant(x, y))
+    }
+
+    /// Adds a value to the constant pool, unless an identical one is already there, and returns its index.
+    fn add_constant(&mut self, value: Value) -> usize {
+        if let Some(index) = self
+            .constants
+            .iter()
+            .position(|constant| same_constant(constant, &value))
+        {
+            return index;
+        }
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Removes ops whose effects are immediately undone, such as pushing a constant and then dropping it. Reading a variable could fail, so it's never removed. There are no jumps to fix up, and the constants are left as they are.
+    fn remove_dead_ops(&mut self) {
+        let mut ops = Vec::with_capacity(self.ops.len());
+        for op in self.ops.drain(..) {
+            match (ops.last(), op) {
+                (Some(Op::GetConstant(_) | Op::Closure(_) | Op::Dup), Op::Drop) => {
+                    ops.pop();
+                }
+                _ => ops.push(op),
+            }
+        }
+        self.ops = ops;
+    }
+
+    /// Gives every variable this code declares a local slot, and switches the ops naming those variables over to their slots. Nested code has its own slots, so it is left alone.
+    fn assign_slots(&mut self) {
+        let mut locals = IndexSet::new();
+        for op in &mut self.ops {
+            *op = match *op {
+                Op::Declare(ident) => Op::Declare(insert_index(&mut locals, ident)),
+                Op::Shadow(ident) => Op::Shadow(insert_index(&mut locals, ident)),
+                op => op,
+            };
+        }
+        for op in &mut self.ops {
+            *op = match *op {
+                Op::GetIdent(ident) => locals.get_index_of(&ident).map_or(*op, Op::GetLocal),
+                Op::Assign(ident) => locals.get_index_of(&ident).map_or(*op, Op::AssignLocal),
+                op => op,
+            };
+        }
+        self.locals = locals.into_iter().collect();
+    }
+
+    /// Returns whether this code can be called with the given number of arguments.
+    pub fn accepts(&self, num_params: usize, num_args: usize) -> bool {
+        num_args >= num_params && (self.variadic || num_args <= num_params + self.num_optional)
+    }
+}
+
+/// Compares constants for sharing, which unlike [`Value`]'s `PartialEq` treats identical bytecode as equal.
+fn same_constant(x: &Value, y: &Value) -> bool {
+    match (x, y) {
+        (Value::Bytecode(x, x_params), Value::Bytecode(y, y_params)) => {
+            x_params == y_params && x.same_code(y)
+        }
+        _ => x == y,
+    }
 }
 
 /// A boolean flag that signals whether the return value for an expression should be generated.
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum Return {
+pub enum Return {
     /// Don't generate instructions to push a return a value.
     Discard,
     /// Generate instructions to push a return a value.
@@ -164,16 +819,41 @@ enum Return {
 // }
 
 impl Code {
-    fn add_expr(&mut self, expr: Expr, ident_list: &mut IndexSet<Ident>, return_mode: Return) {
+    /// Adds the ops for an expression. With [`Return::Keep`] they push exactly one value, and with [`Return::Discard`] none, which assignments rely on when they `Dup` the value of their right-hand side.
+    fn add_expr(
+        &mut self,
+        expr: Expr,
+        ident_list: &mut IndexSet<Ident>,
+        builtins: &Builtins,
+        return_mode: Return,
+    ) {
         let does_return = return_mode == Return::Keep;
         match expr {
+            Expr::None => {
+                if does_return {
+                    let index = self.add_constant(Value::None);
+                    self.ops.push(Op::GetConstant(index));
+                }
+            }
             Expr::Number(val) => {
                 if does_return {
-                    self.constants.push(Value::Number(val.into()));
-                    let index = self.constants.len() - 1;
+                    let index = self.add_constant(Value::Number(val.into()));
                     self.ops.push(Op::GetConstant(index));
                 }
             }
+            Expr::String(val) => {
+                if does_return {
+                    let index = self.add_constant(Value::Str(val));
+                    self.ops.push(Op::GetConstant(index));
+                }
+            }
+            Expr::Negate(operand) => {
+                self.add_expr(*operand, ident_list, builtins, Return::Keep);
+                self.ops.push(Op::Negate);
+                if !does_return {
+                    self.ops.push(Op::Drop);
+                }
+            }
             Expr::Identifier(name) => {
                 if does_return {
                     let name_index = insert_index(ident_list, name);
@@ -181,7 +861,7 @@ impl Code {
                 }
             }
             Expr::Assignment(lhs, rhs) => {
-                self.add_expr(*rhs, ident_list, Return::Keep);
+                self.add_expr(*rhs, ident_list, builtins, Return::Keep);
                 if does_return {
                     self.ops.push(Op::Dup);
                 }
@@ -189,17 +869,24 @@ impl Code {
                 self.ops.push(Op::Assign(name_index));
             }
             Expr::Declaration(lhs, rhs) => {
-                self.add_expr(*rhs, ident_list, Return::Keep);
+                self.add_expr(*rhs, ident_list, builtins, Return::Keep);
                 if does_return {
                     self.ops.push(Op::Dup);
                 }
                 let name_index = insert_index(ident_list, lhs);
                 self.ops.push(Op::Declare(name_index));
             }
+            Expr::Shadow(lhs, rhs) => {
+                self.add_expr(*rhs, ident_list, builtins, Return::Keep);
+                if does_return {
+                    self.ops.push(Op::Dup);
+                }
+                let name_index = insert_index(ident_list, lhs);
+                self.ops.push(Op::Shadow(name_index));
+            }
             Expr::Block(exprs) => {
-                let code = Self::compile(exprs, ident_list, return_mode);
-                self.constants.push(Value::Bytecode(code, 0));
-                let index = self.constants.len() - 1;
+                let code = Self::compile(exprs, ident_list, builtins, return_mode);
+                let index = self.add_constant(Value::Bytecode(Rc::new(code), 0));
                 self.ops.push(Op::GetConstant(index));
                 // A block has no arguments to read from the stack.
                 self.ops.push(Op::Call(0));
@@ -207,25 +894,65 @@ impl Code {
             Expr::Lambda(params, body) => {
                 if does_return {
                     let mut code = Self::default();
-                    let num_params = params.len();
+                    let num_required = params.required.len();
+                    let mut names = params.required;
+                    // Fill in missing optional arguments first, so that every argument after them is known to be extra.
+                    code.num_optional = params.optional.len();
+                    for (position, (name, default)) in (num_required..).zip(params.optional) {
+                        let default =
+                            Self::compile(vec![default], ident_list, builtins, Return::Keep);
+                        let index = code.add_constant(Value::Bytecode(Rc::new(default), 0));
+                        code.ops.push(Op::DefaultArg(position, index));
+                        names.push(name);
+                    }
+                    let mut param_indices = Vec::new();
+                    // The extra arguments sit on top of the stack, above the fixed ones.
+                    if let Some(rest) = params.rest {
+                        code.variadic = true;
+                        code.ops.push(Op::CollectRest(names.len()));
+                        let name_index = insert_index(ident_list, rest);
+                        code.ops.push(Op::Declare(name_index));
+                        param_indices.push(name_index);
+                    }
                     // Arguments pushed off the stack will be reversed.
-                    for param in params.into_iter().rev() {
+                    for param in names.into_iter().rev() {
                         let name_index = insert_index(ident_list, param);
                         code.ops.push(Op::Declare(name_index));
+                        param_indices.push(name_index);
+                    }
+                    code.add_expr(*body, ident_list, builtins, Return::Keep);
+                    code.remove_dead_ops();
+                    code.assign_slots();
+                    let mut free_idents = IndexSet::new();
+                    code.referenced_idents(&mut free_idents);
+                    code.free_idents = free_idents
+                        .into_iter()
+                        .filter(|ident| !param_indices.contains(ident))
+                        .collect();
+                    let needs_captures = !code.free_idents.is_empty();
+                    let index = self.add_constant(Value::Bytecode(Rc::new(code), num_required));
+                    if needs_captures {
+                        self.ops.push(Op::Closure(index));
+                    } else {
+                        self.ops.push(Op::GetConstant(index));
                     }
-                    code.add_expr(*body, ident_list, Return::Keep);
-                    self.constants.push(Value::Bytecode(code, num_params));
-                    let index = self.constants.len() - 1;
-                    self.ops.push(Op::GetConstant(index));
                 }
             }
             Expr::Application(func, args) => {
                 let num_args = args.len();
                 for arg in args {
-                    self.add_expr(arg, ident_list, Return::Keep);
+                    self.add_expr(arg, ident_list, builtins, Return::Keep);
+                }
+                match *func {
+                    Expr::Identifier(ref name) if builtins.contains_key(name.as_str()) => {
+                        self.ops
+                            .push(Op::CallBuiltin(builtins[name.as_str()], num_args));
+                    }
+                    func => {
+                        self.add_expr(func, ident_list, builtins, Return::Keep);
+                        self.ops.push(Op::Call(num_args));
+                    }
                 }
-                self.add_expr(*func, ident_list, Return::Keep);
-                self.ops.push(Op::Call(num_args));
                 if !does_return {
                     self.ops.push(Op::Drop);
                 }
@@ -236,33 +963,371 @@ impl Code {
     fn compile(
         mut exprs: Vec<Expr>,
         ident_list: &mut IndexSet<Ident>,
+        builtins: &Builtins,
         return_mode: Return,
     ) -> Self {
         let does_return = return_mode == Return::Keep;
         let mut code = Self::default();
         if let Some(last_expr) = exprs.pop() {
             for expr in exprs {
-                code.add_expr(expr, ident_list, Return::Discard);
+                code.add_expr(expr, ident_list, builtins, Return::Discard);
             }
-            code.add_expr(last_expr, ident_list, return_mode);
+            code.add_expr(last_expr, ident_list, builtins, return_mode);
         } else if does_return {
             code.constants = vec![Value::None];
             code.ops = vec![Op::GetConstant(0)];
         }
+        code.remove_dead_ops();
+        code.assign_slots();
         code
     }
 }
 
 /// Compiles a series of [`Expr`]s into a [`Code`] object and a list of identifiers used.
 pub fn compile(exprs: Vec<Expr>) -> (Code, IndexSet<Ident>) {
+    compile_with(exprs, Return::Discard)
+}
+
+/// Like [`compile`], but with control over whether the value of the last expression is left on the stack once the code finishes running.
+pub fn compile_with(exprs: Vec<Expr>, return_mode: Return) -> (Code, IndexSet<Ident>) {
+    let mut bound = IndexSet::new();
+    for expr in &exprs {
+        bound_names(expr, true, &mut bound);
+    }
+    let builtins = INTRINSIC_IDENTS
+        .into_iter()
+        .filter(|(name, _)| !bound.contains(*name))
+        .collect();
     let mut idents = IndexSet::new();
-    let code = Code::compile(exprs, &mut idents, Return::Discard);
+    let code = Code::compile(exprs, &mut idents, &builtins, return_mode);
     (code, idents)
 }
 
+/// The builtins that nothing in the program rebinds, by name. Their names are resolved while compiling, so that calls use [`Op::CallBuiltin`] rather than looking them up in scope.
+type Builtins = HashMap<&'static str, Intrinsic>;
+
+/// Adds every name the expression declares or assigns to, including within nested expressions, in the order they first appear. Lambda parameters are included if `params` is set.
+fn bound_names<'a>(expr: &'a Expr, params: bool, names: &mut IndexSet<&'a str>) {
+    match expr {
+        Expr::None | Expr::Number(_) | Expr::String(_) | Expr::Identifier(_) => {}
+        Expr::Negate(operand) => bound_names(operand, params, names),
+        Expr::Assignment(name, rhs) | Expr::Declaration(name, rhs) | Expr::Shadow(name, rhs) => {
+            names.insert(name);
+            bound_names(rhs, params, names);
+        }
+        Expr::Block(exprs) => {
+            for expr in exprs {
+                bound_names(expr, params, names);
+            }
+        }
+        Expr::Application(func, args) => {
+            bound_names(func, params, names);
+            for arg in args {
+                bound_names(arg, params, names);
+            }
+        }
+        Expr::Lambda(lambda_params, body) => {
+            if params {
+                names.extend(lambda_params.required.iter().map(String::as_str));
+                names.extend(lambda_params.optional.iter().map(|(name, _)| name.as_str()));
+                names.extend(lambda_params.rest.as_deref());
+            }
+            for (_, default) in &lambda_params.optional {
+                bound_names(default, params, names);
+            }
+            bound_names(body, params, names);
+        }
+    }
+}
+
+/// Returns the names of builtins that the program declares or assigns to, which hides them from the rest of the scope. Doing so is allowed, but likely by accident.
+pub fn shadowed_builtins(exprs: &[Expr]) -> Vec<&'static str> {
+    let mut assigned = IndexSet::new();
+    for expr in exprs {
+        bound_names(expr, false, &mut assigned);
+    }
+    assigned
+        .into_iter()
+        .filter_map(|name| {
+            INTRINSIC_IDENTS
+                .iter()
+                .find(|(builtin, _)| *builtin == name)
+                .map(|(builtin, _)| *builtin)
+        })
+        .collect()
+}
+
+/// Returns whether the last expression of a program is a value with no side effects, such as a literal or a variable, which will be thrown away unless the program is compiled with [`Return::Keep`]. Such an expression is almost certainly a mistake, like forgetting to `print` it.
+pub fn discards_result(exprs: &[Expr]) -> bool {
+    exprs.last().is_some_and(is_pure)
+}
+
+/// Whether evaluating the expression can't do anything besides produce a value. Calls and blocks could do anything, so they're never considered pure.
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::None | Expr::Number(_) | Expr::String(_) | Expr::Identifier(_) | Expr::Lambda(..) => {
+            true
+        }
+        Expr::Negate(operand) => is_pure(operand),
+        _ => false,
+    }
+}
+
+/// Describes the identifier table and the constant pool of compiled code, including the constants of any bytecode nested within it, for debugging the compiler.
+pub fn dump_symbols(code: &Code, idents: &IndexSet<Ident>) -> String {
+    let mut dump = String::from("identifiers:\n");
+    for (index, ident) in idents.iter().enumerate() {
+        dump += &format!("    {}: {}\n", index, ident);
+    }
+    dump += "constants:\n";
+    dump_constants(code, 1, &mut dump);
+    dump
+}
+
+fn dump_constants(code: &Code, depth: usize, dump: &mut String) {
+    let indent = "    ".repeat(depth);
+    for (index, constant) in code.constants.iter().enumerate() {
+        *dump += &format!("{}{}: {}\n", indent, index, constant);
+        if let Value::Bytecode(nested, _) = constant {
+            dump_constants(nested, depth + 1, dump);
+        }
+    }
+}
+
 fn insert_index<T>(set: &mut IndexSet<T>, value: T) -> usize
 where
     T: Eq + std::hash::Hash,
 {
     set.insert_full(value).0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_nested_lists() {
+        let list = Value::List(vec![
+            Value::Number(1_isize.into()),
+            Value::List(vec![Value::Str("a".to_owned()), Value::List(vec![])]),
+        ]);
+        assert_eq!(list.to_string(), r#"[1, ["a", []]]"#);
+        assert_eq!(Value::Str("a".to_owned()).to_string(), "a");
+    }
+
+    #[test]
+    fn symbols() {
+        use crate::parse::parse;
+        let (code, idents) = compile(parse("x := 5 f := (a) => add(a x) f(x) y := 5").unwrap());
+        assert_eq!(
+            dump_symbols(&code, &idents),
+            "identifiers:
+    0: x
+    1: a
+    2: f
+    3: y
+constants:
+    0: 5
+    1: <1 argument function>
+"
+        );
+        let (code, idents) = compile(parse("z := { { 1 } }").unwrap());
+        assert_eq!(
+            dump_symbols(&code, &idents),
+            "identifiers:
+    0: z
+constants:
+    0: <0 argument function>
+        0: <0 argument function>
+            0: 1
+"
+        );
+    }
+
+    #[test]
+    fn display_deep_list() {
+        let mut list = Value::None;
+        for _ in 0..100_000 {
+            list = Value::List(vec![list]);
+        }
+        let text = list.to_string();
+        let expected_prefix = "[".repeat(DISPLAY_DEPTH_LIMIT);
+        assert_eq!(
+            text,
+            format!("{}...{}", expected_prefix, "]".repeat(DISPLAY_DEPTH_LIMIT))
+        );
+        // Dropping the list recursively would overflow the stack too, so take it apart by hand.
+        while let Value::List(mut inner) = list {
+            list = inner.pop().unwrap_or(Value::None);
+        }
+    }
+
+    #[test]
+    fn display_truncated_list() {
+        let list = |n: isize| Value::List((0..n).map(|i| Value::Number(i.into())).collect());
+        assert_eq!(list(3).truncated(3).to_string(), "[0, 1, 2]");
+        assert_eq!(list(5).truncated(2).to_string(), "[0, 1, ... (3 more)]");
+        assert_eq!(list(5).truncated(0).to_string(), "[... (5 more)]");
+        let nested = Value::List(vec![list(4), list(1)]);
+        assert_eq!(
+            nested.truncated(2).to_string(),
+            "[[0, 1, ... (2 more)], [0]]"
+        );
+        assert_eq!(
+            list(150).truncated(100).to_string().matches(", ").count(),
+            100
+        );
+    }
+
+    #[test]
+    fn identical_constants_are_shared() {
+        use crate::parse::parse;
+        let source = "cmp := (a b) => sub(a b) x := fold(list() cmp) y := fold(list() (a b) => sub(a b)) z := fold(list() (a b) => sub(a b))";
+        let (code, _) = compile(parse(source).unwrap());
+        // Three copies of the lambda, which would otherwise take up three constants.
+        assert_eq!(code.constants.len(), 1);
+        let (code, _) =
+            compile(parse("a := { 1 } b := { 1 } c := { 2 } d := \"s\" e := \"s\"").unwrap());
+        assert_eq!(code.constants.len(), 3);
+        // Lambdas that differ only in what they capture stay separate.
+        let (code, _) = compile(parse("f := () => x g := () => y").unwrap());
+        assert_eq!(code.constants.len(), 2);
+    }
+
+    #[test]
+    fn none_is_a_constant() {
+        use crate::parse::parse;
+        let (code, _) = compile_with(parse("none").unwrap(), Return::Keep);
+        assert_eq!(code.ops, [Op::GetConstant(0)]);
+        assert!(matches!(code.constants[..], [Value::None]));
+    }
+
+    #[test]
+    fn builtin_calls() {
+        use crate::parse::parse;
+        let (code, _) = compile_with(parse("add(1 2)").unwrap(), Return::Keep);
+        assert_eq!(
+            code.ops,
+            [
+                Op::GetConstant(0),
+                Op::GetConstant(1),
+                Op::CallBuiltin(Intrinsic::Add, 2)
+            ]
+        );
+        // Once the name is bound anywhere, every call through it has to look it up.
+        for source in ["add(1 2) add := mul", "f := (add) => add(1 2)"] {
+            let (code, _) = compile_with(parse(source).unwrap(), Return::Keep);
+            let mut ops = code
+                .ops
+                .iter()
+                .chain(code.constants.iter().flat_map(|c| match c {
+                    Value::Bytecode(code, _) => &code.ops[..],
+                    _ => &[],
+                }));
+            assert!(
+                !ops.any(|op| matches!(op, Op::CallBuiltin(..))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn locals_get_slots() {
+        use crate::parse::parse;
+        let (code, idents) = compile(parse("y := x x := y x = 2").unwrap());
+        let (x, y) = (idents.get_index_of("x"), idents.get_index_of("y"));
+        assert_eq!(code.locals, [y.unwrap(), x.unwrap()]);
+        assert_eq!(
+            code.ops,
+            [
+                Op::GetLocal(1),
+                Op::Declare(0),
+                Op::GetLocal(0),
+                Op::Declare(1),
+                Op::GetConstant(0),
+                Op::AssignLocal(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowed_builtin_names() {
+        use crate::parse::parse;
+        let shadowed = |source| shadowed_builtins(&parse(source).unwrap());
+        assert_eq!(shadowed("print := 3"), ["print"]);
+        assert_eq!(
+            shadowed("x := 1 { len = 2 map := (list) => list } let len = 3"),
+            ["len", "map"]
+        );
+        assert!(shadowed("f := (print) => print x = 5").is_empty());
+    }
+
+    #[test]
+    fn dead_ops_removed() {
+        let mut code = Code {
+            ops: vec![
+                Op::GetConstant(0),
+                Op::Dup,
+                Op::Drop,
+                Op::Drop,
+                Op::GetIdent(0),
+                Op::Drop,
+                Op::GetConstant(1),
+                Op::Closure(2),
+                Op::Dup,
+                Op::Declare(1),
+                Op::Drop,
+                Op::Drop,
+            ],
+            ..Code::default()
+        };
+        code.remove_dead_ops();
+        assert_eq!(
+            code.ops,
+            [
+                Op::GetIdent(0),
+                Op::Drop,
+                Op::GetConstant(1),
+                Op::Closure(2),
+                Op::Dup,
+                Op::Declare(1),
+                Op::Drop,
+                Op::Drop
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_block_value_follows_return_mode() {
+        use crate::parse::parse;
+        let block_ops = |return_mode| {
+            let (code, _) = compile_with(parse("x := 1 { 2 x }").unwrap(), return_mode);
+            match &code.constants[..] {
+                [_, Value::Bytecode(block, 0)] => (block.ops.clone(), code.ops),
+                constants => panic!("unexpected constants {:?}", constants),
+            }
+        };
+        let (block, main) = block_ops(Return::Keep);
+        assert_eq!(block, [Op::GetIdent(0)]);
+        assert_eq!(main.last(), Some(&Op::Call(0)));
+        let (block, main) = block_ops(Return::Discard);
+        assert_eq!(block, []);
+        assert_eq!(main.last(), Some(&Op::Call(0)));
+    }
+
+    #[test]
+    fn discarded_results() {
+        use crate::parse::parse;
+        let discards = |source| discards_result(&parse(source).unwrap());
+        assert!(discards("x := 5 x"));
+        assert!(discards("\"hello\""));
+        assert!(discards("-x"));
+        assert!(discards("(a) => a"));
+        assert!(!discards("x := 5"));
+        assert!(!discards("print(5)"));
+        assert!(!discards("{ 5 }"));
+        assert!(!discards("-f()"));
+        assert!(!discards(""));
+    }
+}
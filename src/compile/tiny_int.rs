@@ -1,9 +1,10 @@
 use num_bigint::BigInt;
 use num_traits::{Signed, Zero};
 use std::convert::{From, TryFrom, Into};
-use std::{fmt, ops};
+use std::{cmp::Ordering, fmt, ops};
 
-#[derive(Clone, Debug, PartialEq)]
+/// An integer which is stored inline when it fits in an `isize`. Every value has exactly one representation, so that equality and hashing don't depend on how it was computed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TinyInt {
     Inline(isize),
     Heap(BigInt),
@@ -85,11 +86,29 @@ impl ops::Neg for TinyInt {
     fn neg(self) -> Self {
         match self {
             Inline(x) => x.checked_neg().map_or(Heap(-BigInt::from(x)), Inline),
-            // TODO: Should we demote to Inline if `h.neg()` is `isize::MAX`?
-            Heap(h) => Heap(h.neg()),
+            // Negating `isize::MAX + 1` gives `isize::MIN`, which needs to be demoted.
+            Heap(h) => h.neg().into(),
         }
     }
 }
+impl Ord for TinyInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Inline(x), Inline(y)) => x.cmp(y),
+            (Heap(x), Heap(y)) => x.cmp(y),
+            // Since the representation is canonical, a heap value is always out of the range of any inline one.
+            (Heap(h), Inline(_)) if h.is_negative() => Ordering::Less,
+            (Heap(_), Inline(_)) => Ordering::Greater,
+            (Inline(_), Heap(h)) if h.is_negative() => Ordering::Greater,
+            (Inline(_), Heap(_)) => Ordering::Less,
+        }
+    }
+}
+impl PartialOrd for TinyInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 impl fmt::Display for TinyInt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -225,6 +244,20 @@ mod tests {
         );
     }
     #[test]
+    fn neg_demote() {
+        assert_eq!(-Heap(BigInt::from(isize::MAX) + 1), Inline(isize::MIN));
+    }
+    #[test]
+    fn ordering() {
+        let big = Heap(BigInt::from(isize::MAX) + 1);
+        let small = Heap(BigInt::from(isize::MIN) - 1);
+        assert!(Inline(-3) < Inline(2));
+        assert!(big > Inline(isize::MAX));
+        assert!(small < Inline(isize::MIN));
+        assert!(small < big);
+        assert!(Inline(0) > small && Inline(0) < big);
+    }
+    #[test]
     fn div_demote() {
         assert_eq!(
             Heap(BigInt::from(isize::MAX)) / Inline(2),
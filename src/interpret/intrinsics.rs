@@ -1,11 +1,40 @@
 use super::macros::double_try;
-use super::{ExecResult, Executor, ScriptError, Value};
-use crate::compile::TinyInt;
+use super::random::Rng;
+use super::{ExecResult, Executor, ScriptError, ScriptResult, Value};
+use crate::compile::{Code, Key, Lazy, Op, TinyInt, INTRINSIC_IDENTS};
+use num_bigint::BigInt;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::io;
+use std::mem;
+use std::rc::Rc;
 //use num_traits::{Signed, Zero};
 
+/// Prints a value on its own line. The output is flushed afterwards, so that it's never held back behind text from `write`.
 pub fn print(exec: &mut Executor) -> ExecResult<Value> {
     let val = exec.pop_stack()?;
-    println!("{}", val);
+    // Like `println!`, but failing to write shouldn't take down the interpreter.
+    exec.globals.output.with_writer(|writer| {
+        let _ = writeln!(writer, "{}", val);
+        let _ = writer.flush();
+    });
+    Ok(Ok(Value::None))
+}
+
+/// Prints a value without a newline. Since stdout is line buffered, the text may not show up until the next newline or a call to `flush`.
+pub fn write(exec: &mut Executor) -> ExecResult<Value> {
+    let val = exec.pop_stack()?;
+    exec.globals.output.with_writer(|writer| {
+        let _ = write!(writer, "{}", val);
+    });
+    Ok(Ok(Value::None))
+}
+
+#[allow(clippy::unnecessary_wraps)]
+pub fn flush(exec: &mut Executor) -> ExecResult<Value> {
+    exec.globals.output.with_writer(|writer| {
+        let _ = writer.flush();
+    });
     Ok(Ok(Value::None))
 }
 
@@ -16,11 +45,32 @@ pub fn while_loop(exec: &mut Executor) -> ExecResult<Value> {
         (Value::Bytecode(condition, 0), Value::Bytecode(body, 0)) => {
             let mut output = Value::None;
             loop {
-                let cond_value = double_try!(exec.run_code_object(condition.clone())); // 5.16% clone, 91.65% run_code_object
+                let cond_value = double_try!(exec.run_code_object(condition.clone(), 0)); // 5.16% clone, 91.65% run_code_object
                 if !cond_value.truthiness() { // 1.11% truthiness
                     break;
                 }
-                output = double_try!(exec.run_code_object(body.clone()));
+                output = double_try!(exec.run_code_object(body.clone(), 0));
+            }
+            Ok(output)
+        }
+        _ => Err(ScriptError::ArgumentType),
+    };
+    Ok(op_result)
+}
+
+/// Like `while`, but runs the body once before checking the condition for the first time.
+pub fn do_while(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    let op_result = match (val1, val2) {
+        (Value::Bytecode(body, 0), Value::Bytecode(condition, 0)) => {
+            let mut output;
+            loop {
+                output = double_try!(exec.run_code_object(body.clone(), 0));
+                let cond_value = double_try!(exec.run_code_object(condition.clone(), 0));
+                if !cond_value.truthiness() {
+                    break;
+                }
             }
             Ok(output)
         }
@@ -29,16 +79,40 @@ pub fn while_loop(exec: &mut Executor) -> ExecResult<Value> {
     Ok(op_result)
 }
 
+/// Runs the body over and over until it calls `break`, and returns the value given to `break`.
+pub fn infinite_loop(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Bytecode(body, 0) = exec.pop_stack()? {
+        loop {
+            match exec.run_code_object(body.clone(), 0)? {
+                Ok(_) => {}
+                Err(ScriptError::Break(value)) => {
+                    // The error was handled, so it shouldn't be reported later.
+                    exec.globals.traceback = None;
+                    return Ok(Ok(*value));
+                }
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Stops the innermost `loop`, making it return the given value.
+pub fn break_loop(exec: &mut Executor) -> ExecResult<Value> {
+    let val = exec.pop_stack()?;
+    Ok(Err(ScriptError::Break(Box::new(val))))
+}
+
 macro_rules! arithmetic_intrinsic {
     ($self:ident, $oper:expr) => {
         pub fn $self(exec: &mut Executor) -> ExecResult<Value> {
             let val2 = exec.pop_stack()?;
             let val1 = exec.pop_stack()?;
-            let op_result = match (val1, val2) {
-                (Value::Number(x), Value::Number(y)) => Ok($oper(x, y)),
-                _ => Err(ScriptError::ArgumentType),
-            };
-            Ok(op_result)
+            match (val1, val2) {
+                (Value::Number(x), Value::Number(y)) => exec.check_overflow($oper(x, y)),
+                _ => Ok(Err(ScriptError::ArgumentType)),
+            }
         }
     };
 }
@@ -133,23 +207,28 @@ pub fn push(exec: &mut Executor) -> ExecResult<Value> {
     }
 }
 
+/// Returns the number of elements in a list or the number of characters in a string. Other values have no length, and are an error rather than giving `0`, since that's almost always a mistake.
 pub fn len(exec: &mut Executor) -> ExecResult<Value> {
-    if let Value::List(list) = exec.pop_stack()? {
-        Ok(Ok(Value::Number(list.len().into())))
-    } else {
-        Ok(Err(ScriptError::ArgumentType))
-    }
+    let len = match exec.pop_stack()? {
+        Value::List(list) => list.len(),
+        // Count characters rather than bytes, so that the length doesn't depend on the encoding.
+        Value::Str(s) => s.chars().count(),
+        _ => return Ok(Err(ScriptError::ArgumentType)),
+    };
+    Ok(Ok(Value::Number(len.into())))
 }
 
 pub fn map(exec: &mut Executor) -> ExecResult<Value> {
     let val2 = exec.pop_stack()?;
     let val1 = exec.pop_stack()?;
     if let Value::Bytecode(code, 1) = val1 {
-        if let Value::List(list) = val2 {
+        if let Value::Lazy(lazy) = val2 {
+            Ok(Ok(Value::Lazy(Rc::new(Lazy::Map(code, lazy)))))
+        } else if let Value::List(list) = val2 {
             let mut results = Vec::with_capacity(list.len());
             for item in list {
                 exec.stack.push(item);
-                let mapped_item = double_try!(exec.run_code_object(code.clone()));
+                let mapped_item = double_try!(exec.run_code_object(code.clone(), 1));
                 results.push(mapped_item);
             }
             Ok(Ok(Value::List(results)))
@@ -161,11 +240,12 @@ pub fn map(exec: &mut Executor) -> ExecResult<Value> {
     }
 }
 
+/// Folds a list from the right, using its last element as the initial value, so that `fold(f [a b c])` is `f(a f(b c))`. An empty list gives `None`. See `fold_left` and `fold_right` to give an initial value and pick the direction explicitly. Since it starts from the end, a lazy sequence is read into a list first.
 pub fn fold(exec: &mut Executor) -> ExecResult<Value> {
     let val2 = exec.pop_stack()?;
     let val1 = exec.pop_stack()?;
     if let Value::Bytecode(code, 2) = val1 {
-        if let Value::List(mut list) = val2 {
+        if let Some(mut list) = double_try!(read_all(exec, val2)) {
             let mut accum = match list.pop() {
                 Some(v) => v,
                 None => return Ok(Ok(Value::None)),
@@ -173,7 +253,7 @@ pub fn fold(exec: &mut Executor) -> ExecResult<Value> {
             for item in list.into_iter().rev() {
                 exec.stack.push(item);
                 exec.stack.push(accum.clone());
-                accum = double_try!(exec.run_code_object(code.clone()));
+                accum = double_try!(exec.run_code_object(code.clone(), 2));
             }
             Ok(Ok(accum))
         } else {
@@ -188,11 +268,13 @@ pub fn filter(exec: &mut Executor) -> ExecResult<Value> {
     let val2 = exec.pop_stack()?;
     let val1 = exec.pop_stack()?;
     if let Value::Bytecode(code, 1) = val1 {
-        if let Value::List(list) = val2 {
+        if let Value::Lazy(lazy) = val2 {
+            Ok(Ok(Value::Lazy(Rc::new(Lazy::Filter(code, lazy)))))
+        } else if let Value::List(list) = val2 {
             let mut results = vec![];
             for item in list {
                 exec.stack.push(item.clone());
-                if double_try!(exec.run_code_object(code.clone())).truthiness() {
+                if double_try!(exec.run_code_object(code.clone(), 1)).truthiness() {
                     results.push(item);
                 }
             }
@@ -213,7 +295,7 @@ pub fn zip(exec: &mut Executor) -> ExecResult<Value> {
             Ok(Ok(Value::List(
                 list1
                     .into_iter()
-                    .zip(list2.into_iter())
+                    .zip(list2)
                     .map(|(a, b)| Value::List(vec![a, b]))
                     .collect(),
             )))
@@ -225,26 +307,283 @@ pub fn zip(exec: &mut Executor) -> ExecResult<Value> {
     }
 }
 
+/// Converts a possibly negative index into a list of the given length to a position within it. Negative indices count backwards from the end, so `-1` is the last element.
+fn resolve_index(n: TinyInt, len: usize) -> Option<usize> {
+    let index = if n.is_negative() {
+        len.checked_sub((-n).try_into().ok()?)?
+    } else {
+        n.try_into().ok()?
+    };
+    (index < len).then_some(index)
+}
+
+/// Returns the element of a list or the character of a string at the index, or `None` if it is out of bounds. Strings are indexed by character rather than by byte.
 pub fn at(exec: &mut Executor) -> ExecResult<Value> {
     let val2 = exec.pop_stack()?;
     let val1 = exec.pop_stack()?;
-    if let Value::List(list) = val1 {
-        if let Value::Number(n) = val2 {
-            let none = Ok(Ok(Value::None));
-            let index = if n.is_negative() {
-                match (-n).try_into() {
-                    Ok(neg_index) => match list.len().checked_sub(neg_index) {
-                        Some(index) => index,
-                        None => return none,
-                    },
-                    Err(_) => return none,
+    match (val1, val2) {
+        (Value::List(list), Value::Number(n)) => {
+            let item = resolve_index(n, list.len()).and_then(|index| list.get(index));
+            Ok(Ok(item.cloned().unwrap_or(Value::None)))
+        }
+        (Value::Str(s), Value::Number(n)) => {
+            let item = resolve_index(n, s.chars().count()).and_then(|index| s.chars().nth(index));
+            Ok(Ok(item.map_or(Value::None, |c| Value::Str(c.into()))))
+        }
+        _ => Ok(Err(ScriptError::ArgumentType)),
+    }
+}
+
+#[allow(clippy::unnecessary_wraps)]
+pub fn args(exec: &mut Executor) -> ExecResult<Value> {
+    let args = exec.globals.args.iter().cloned().map(Value::Str).collect();
+    Ok(Ok(Value::List(args)))
+}
+
+/// Looks up an environment variable, returning `None` if it is unset or not valid unicode. This reaches into the host system, so it is only available when the executor has been granted system access.
+pub fn env(exec: &mut Executor) -> ExecResult<Value> {
+    let val = exec.pop_stack()?;
+    if !exec.globals.system_access {
+        return Ok(Err(ScriptError::AccessDenied));
+    }
+    if let Value::Str(name) = val {
+        Ok(Ok(std::env::var(name).map_or(Value::None, Value::Str)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Halts the script with the given exit code. Codes outside of `0..=255`, including negative ones, become 255, so that a script signalling failure never appears to succeed. Rather than terminating the process, this raises [`ScriptError::Exit`] so that whoever is running the executor decides how to exit.
+pub fn exit(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Number(n) = exec.pop_stack()? {
+        let code = usize::try_from(n).map_or(255, |code| code.min(255));
+        // Anything printed so far should make it out before the process exits.
+        exec.globals.output.with_writer(|writer| {
+            let _ = writer.flush();
+        });
+        Ok(Err(ScriptError::Exit(
+            code.try_into().expect("exit code was clamped"),
+        )))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the number of milliseconds since the executor was created. This uses a monotonic clock, so it's unaffected by changes to the system time.
+#[allow(clippy::unnecessary_wraps)]
+pub fn clock(exec: &mut Executor) -> ExecResult<Value> {
+    let millis = exec
+        .globals
+        .start_time
+        .map_or(0, |start| start.elapsed().as_millis());
+    Ok(Ok(Value::Number(
+        usize::try_from(millis).unwrap_or(usize::MAX).into(),
+    )))
+}
+
+/// Returns a uniformly random integer from `0` up to but not including the argument, which must be positive.
+pub fn random(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Number(bound) = exec.pop_stack()? {
+        Ok(exec
+            .globals
+            .rng
+            .below(&bound)
+            .map(Value::Number)
+            .ok_or(ScriptError::ArgumentValue))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Reseeds the random number generator, so that the numbers which follow are the same on every run.
+pub fn seed(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Number(seed) = exec.pop_stack()? {
+        exec.globals.rng = Rng::from_number(&seed);
+        Ok(Ok(Value::None))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the list in a random order, drawing from the same generator as `random`.
+pub fn shuffle(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::List(mut list) = exec.pop_stack()? {
+        // Fisher-Yates, working down from the end of the list.
+        for i in (1..list.len()).rev() {
+            let j = exec.globals.rng.index_below(i + 1);
+            list.swap(i, j);
+        }
+        Ok(Ok(Value::List(list)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns a list of `k` elements chosen at random from distinct positions of the list, drawing from the same generator as `random`.
+pub fn sample(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::List(mut list), Value::Number(k)) = (val1, val2) {
+        let k = match usize::try_from(k) {
+            Ok(k) if k <= list.len() => k,
+            _ => return Ok(Err(ScriptError::ArgumentValue)),
+        };
+        // A Fisher-Yates shuffle that stops once the first `k` positions are settled.
+        for i in 0..k {
+            let j = i + exec.globals.rng.index_below(list.len() - i);
+            list.swap(i, j);
+        }
+        list.truncate(k);
+        Ok(Ok(Value::List(list)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the list with every element that is equal to an earlier one removed.
+pub fn unique(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::List(list) = exec.pop_stack()? {
+        let mut results = Distinct::default();
+        for item in list {
+            results.insert(item);
+        }
+        Ok(Ok(Value::List(results.values)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Collects values in order, leaving out any that are equal to one collected earlier. Values with a [`Key`] are found by hashing it, while the rest, which hold functions and so can only be equal to each other, are compared one by one.
+#[derive(Default)]
+struct Distinct {
+    values: Vec<Value>,
+    keys: HashSet<Key>,
+    /// The indices of the values without a key.
+    unkeyed: Vec<usize>,
+}
+
+impl Distinct {
+    fn contains(&self, value: &Value) -> bool {
+        match value.to_key() {
+            Some(key) => self.keys.contains(&key),
+            None => self.unkeyed.iter().any(|&i| self.values[i] == *value),
+        }
+    }
+
+    fn insert(&mut self, value: Value) {
+        match value.to_key() {
+            Some(key) => {
+                if self.keys.insert(key) {
+                    self.values.push(value);
                 }
-            } else if let Ok(index) = n.try_into() {
-                index
-            } else {
-                return none;
-            };
-            Ok(Ok(list.get(index).cloned().unwrap_or(Value::None)))
+            }
+            None => {
+                if !self.contains(&value) {
+                    self.unkeyed.push(self.values.len());
+                    self.values.push(value);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the elements of both lists, in the order they first appear in the first list and then the second, with duplicates removed like `unique`.
+pub fn union(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::List(list1), Value::List(list2)) = (val1, val2) {
+        let mut results = Distinct::default();
+        for item in list1.into_iter().chain(list2) {
+            results.insert(item);
+        }
+        Ok(Ok(Value::List(results.values)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the elements of the first list that are also in the second, in the order of the first list with duplicates removed.
+pub fn intersection(exec: &mut Executor) -> ExecResult<Value> {
+    filter_by_membership(exec, true)
+}
+
+/// Returns the elements of the first list that aren't in the second, in the order of the first list with duplicates removed.
+pub fn difference(exec: &mut Executor) -> ExecResult<Value> {
+    filter_by_membership(exec, false)
+}
+
+fn filter_by_membership(exec: &mut Executor, keep_members: bool) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::List(list1), Value::List(list2)) = (val1, val2) {
+        let mut members = Distinct::default();
+        for item in list2 {
+            members.insert(item);
+        }
+        let mut results = Distinct::default();
+        for item in list1 {
+            if members.contains(&item) == keep_members {
+                results.insert(item);
+            }
+        }
+        Ok(Ok(Value::List(results.values)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Groups the elements of a list by the key the given function computes for each, returning a list of `[key, elements]` pairs in the order each key was first seen. Keys must be `None`, numbers, or strings.
+pub fn group_by(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let Value::Bytecode(code, 1) = val1 {
+        if let Value::List(list) = val2 {
+            let mut groups: Vec<(Value, Vec<Value>)> = vec![];
+            for item in list {
+                exec.stack.push(item.clone());
+                let key = double_try!(exec.run_code_object(code.clone(), 1));
+                if !matches!(key, Value::None | Value::Number(_) | Value::Str(_)) {
+                    return Ok(Err(ScriptError::ArgumentType));
+                }
+                match groups.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, group)) => group.push(item),
+                    None => groups.push((key, vec![item])),
+                }
+            }
+            Ok(Ok(Value::List(
+                groups
+                    .into_iter()
+                    .map(|(key, group)| Value::List(vec![key, Value::List(group)]))
+                    .collect(),
+            )))
+        } else {
+            Ok(Err(ScriptError::ArgumentType))
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Splits a list in a single pass into a list of the elements satisfying the predicate and a list of those which don't, both in their original order.
+pub fn partition(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let Value::Bytecode(code, 1) = val1 {
+        if let Value::List(list) = val2 {
+            let mut matching = vec![];
+            let mut non_matching = vec![];
+            for item in list {
+                exec.stack.push(item.clone());
+                if double_try!(exec.run_code_object(code.clone(), 1)).truthiness() {
+                    matching.push(item);
+                } else {
+                    non_matching.push(item);
+                }
+            }
+            Ok(Ok(Value::List(vec![
+                Value::List(matching),
+                Value::List(non_matching),
+            ])))
         } else {
             Ok(Err(ScriptError::ArgumentType))
         }
@@ -252,3 +591,2187 @@ pub fn at(exec: &mut Executor) -> ExecResult<Value> {
         Ok(Err(ScriptError::ArgumentType))
     }
 }
+
+/// Combines corresponding elements of two lists with a two argument function, stopping at the end of the shorter list.
+pub fn zip_with(exec: &mut Executor) -> ExecResult<Value> {
+    let val3 = exec.pop_stack()?;
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Bytecode(code, 2), Value::List(list1), Value::List(list2)) = (val1, val2, val3) {
+        let mut results = Vec::with_capacity(list1.len().min(list2.len()));
+        for (a, b) in list1.into_iter().zip(list2) {
+            exec.stack.push(a);
+            exec.stack.push(b);
+            results.push(double_try!(exec.run_code_object(code.clone(), 2)));
+        }
+        Ok(Ok(Value::List(results)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Zips any number of lists, given as a list of lists, into a list of lists with one element from each. This stops at the end of the shortest list.
+pub fn zip_n(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::List(lists) = exec.pop_stack()? {
+        let mut iters = Vec::with_capacity(lists.len());
+        for list in lists {
+            if let Value::List(list) = list {
+                iters.push(list.into_iter());
+            } else {
+                return Ok(Err(ScriptError::ArgumentType));
+            }
+        }
+        let min_len = iters.iter().map(ExactSizeIterator::len).min().unwrap_or(0);
+        let results = (0..min_len)
+            .map(|_| Value::List(iters.iter_mut().filter_map(Iterator::next).collect()))
+            .collect();
+        Ok(Ok(Value::List(results)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Turns a list of rows into a list of columns, so that the element at `at(at(grid i) j)` moves to `at(at(result j) i)`. Unlike `zip_n`, which stops at the shortest list, rows of different lengths are an error since they don't form a grid. Rows with no elements have no columns, so they transpose to an empty list.
+pub fn transpose(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::List(rows) = exec.pop_stack()? {
+        let mut iters = Vec::with_capacity(rows.len());
+        for row in rows {
+            if let Value::List(row) = row {
+                iters.push(row.into_iter());
+            } else {
+                return Ok(Err(ScriptError::ArgumentType));
+            }
+        }
+        let width = iters.first().map_or(0, ExactSizeIterator::len);
+        if iters.iter().any(|iter| iter.len() != width) {
+            return Ok(Err(ScriptError::ArgumentValue));
+        }
+        let columns = (0..width)
+            .map(|_| Value::List(iters.iter_mut().filter_map(Iterator::next).collect()))
+            .collect();
+        Ok(Ok(Value::List(columns)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the list with the value inserted so that it ends up at the given index, which may be negative. Since the list grows by one, `0` inserts at the front and `-1` at the back. Indices outside of the new list are an error.
+pub fn insert(exec: &mut Executor) -> ExecResult<Value> {
+    let val3 = exec.pop_stack()?;
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::List(mut list), Value::Number(n)) = (val1, val2) {
+        if let Some(index) = resolve_index(n, list.len() + 1) {
+            list.insert(index, val3);
+            Ok(Ok(Value::List(list)))
+        } else {
+            Ok(Err(ScriptError::ArgumentValue))
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the list without the element at the given index, which may be negative as with `at`. Indices outside of the list are an error.
+pub fn remove(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::List(mut list), Value::Number(n)) = (val1, val2) {
+        if let Some(index) = resolve_index(n, list.len()) {
+            list.remove(index);
+            Ok(Ok(Value::List(list)))
+        } else {
+            Ok(Err(ScriptError::ArgumentValue))
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the list with the element at the given index, which may be negative as with `at`, replaced by the value. Indices outside of the list are an error.
+pub fn set_at(exec: &mut Executor) -> ExecResult<Value> {
+    let val3 = exec.pop_stack()?;
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::List(mut list), Value::Number(n)) = (val1, val2) {
+        if let Some(index) = resolve_index(n, list.len()) {
+            list[index] = val3;
+            Ok(Ok(Value::List(list)))
+        } else {
+            Ok(Err(ScriptError::ArgumentValue))
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Like `set_at`, but replaces the element with the result of calling a one argument function on it.
+pub fn update_at(exec: &mut Executor) -> ExecResult<Value> {
+    let val3 = exec.pop_stack()?;
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::List(mut list), Value::Number(n), Value::Bytecode(code, 1)) = (val1, val2, val3)
+    {
+        if let Some(index) = resolve_index(n, list.len()) {
+            exec.stack.push(mem::replace(&mut list[index], Value::None));
+            list[index] = double_try!(exec.run_code_object(code, 1));
+            Ok(Ok(Value::List(list)))
+        } else {
+            Ok(Err(ScriptError::ArgumentValue))
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Makes room for a list of `n` elements that a builtin was asked to build, which is empty if `n` isn't positive. Lengths past the [list limit](Executor::with_list_limit), or too long to allocate at all, are refused before anything is built.
+fn constructed_list(exec: &Executor, n: TinyInt) -> ScriptResult<(usize, Vec<Value>)> {
+    if n.is_negative() {
+        return Ok((0, vec![]));
+    }
+    let n = match usize::try_from(n) {
+        Ok(n) if exec.globals.list_limit.is_none_or(|limit| n <= limit) => n,
+        _ => return Err(ScriptError::MemoryLimit),
+    };
+    let mut list = Vec::new();
+    list.try_reserve_exact(n)
+        .map_err(|_| ScriptError::MemoryLimit)?;
+    Ok((n, list))
+}
+
+/// Returns a list containing `n` copies of the value, or an empty list if `n` isn't positive.
+pub fn replicate(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let Value::Number(n) = val1 {
+        Ok(constructed_list(exec, n).map(|(n, mut list)| {
+            list.resize(n, val2);
+            Value::List(list)
+        }))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+pub fn seq(exec: &mut Executor) -> ExecResult<Value> {
+    let val1 = exec.pop_stack()?;
+    if let Value::List(list) = val1 {
+        // Check every element up front so that nothing runs if any of them is invalid.
+        if !list
+            .iter()
+            .all(|item| matches!(item, Value::Bytecode(_, 0)))
+        {
+            return Ok(Err(ScriptError::ArgumentType));
+        }
+        let mut output = Value::None;
+        for item in list {
+            if let Value::Bytecode(code, 0) = item {
+                output = double_try!(exec.run_code_object(code, 0));
+            }
+        }
+        Ok(Ok(output))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Wraps a function so that calling it again with arguments equal to those of an earlier call returns the earlier result instead of running it. Calls with arguments that can't be compared by value, such as functions, are always run.
+pub fn memoize(exec: &mut Executor) -> ExecResult<Value> {
+    let val1 = exec.pop_stack()?;
+    if let Value::Bytecode(code, num_params) = val1 {
+        let wrapper = Code {
+            ops: vec![Op::Memoized(0)],
+            num_optional: code.num_optional,
+            variadic: code.variadic,
+            memo: Some(Rc::default()),
+            constants: vec![Value::Bytecode(code, num_params)],
+            ..Code::default()
+        };
+        Ok(Ok(Value::Bytecode(Rc::new(wrapper), num_params)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Wraps a function that takes no arguments so that it only runs the first time it's called, and every later call returns that first result. This is `memoize` for functions that can only be called one way. If the function raises an error, nothing is cached and the next call runs it again.
+pub fn once(exec: &mut Executor) -> ExecResult<Value> {
+    let thunk = exec.pop_stack()?;
+    match &thunk {
+        Value::Bytecode(code, 0) if code.num_optional == 0 && !code.variadic => {
+            exec.stack.push(thunk);
+            memoize(exec)
+        }
+        _ => Ok(Err(ScriptError::ArgumentType)),
+    }
+}
+
+/// Prints the variables in scope and the stack to stderr. If interactive breakpoints are enabled, it then reads variable names from stdin and prints their values until it reads an empty line.
+pub fn breakpoint(exec: &mut Executor) -> ExecResult<Value> {
+    eprintln!("{}", exec.debug_dump());
+    if exec.globals.interactive_breakpoints {
+        eprintln!("Enter a variable name to print its value, or nothing to continue.");
+        let mut line = String::new();
+        loop {
+            eprint!("> ");
+            line.clear();
+            // Treat a closed or unreadable stdin the same as an empty line.
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                break;
+            }
+            let name = line.trim();
+            match exec
+                .globals
+                .idents
+                .get_index_of(name)
+                .map(|ident| exec.lookup_value(ident))
+            {
+                Some(Ok(value)) => eprintln!("{}", value),
+                _ => eprintln!("`{}` is not defined", name),
+            }
+        }
+    }
+    Ok(Ok(Value::None))
+}
+
+macro_rules! type_predicate {
+    ($self:ident, $pattern:pat) => {
+        pub fn $self(exec: &mut Executor) -> ExecResult<Value> {
+            let val = exec.pop_stack()?;
+            Ok(Ok(matches!(val, $pattern).into()))
+        }
+    };
+}
+
+type_predicate! {is_none, Value::None}
+type_predicate! {is_number, Value::Number(_)}
+type_predicate! {is_string, Value::Str(_)}
+type_predicate! {is_list, Value::List(_)}
+type_predicate! {is_callable, Value::Bytecode(..) | Value::Builtin(_)}
+
+/// Returns whether two values are equal. See the [`PartialEq`] implementation of [`Value`] for what that means.
+pub fn eq(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    Ok(Ok((val1 == val2).into()))
+}
+
+/// Returns whether any element of the list is equal to the value.
+pub fn contains(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    match (val1, val2) {
+        (Value::List(list), val2) => Ok(Ok(list.contains(&val2).into())),
+        (Value::Str(s), Value::Str(needle)) => Ok(Ok(s.contains(&needle).into())),
+        _ => Ok(Err(ScriptError::ArgumentType)),
+    }
+}
+
+/// Writes a number in the given base from 2 to 36, using lowercase letters for digits past 9 and a leading `-` for negative numbers.
+pub fn to_radix(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Number(n), Value::Number(base)) = (val1, val2) {
+        let base = match usize::try_from(base) {
+            Ok(base @ 2..=36) => base as u32,
+            _ => return Ok(Err(ScriptError::ArgumentValue)),
+        };
+        let n = match n {
+            TinyInt::Inline(x) => BigInt::from(x),
+            TinyInt::Heap(h) => h,
+        };
+        Ok(Ok(Value::Str(n.to_str_radix(base))))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+pub fn min_by(exec: &mut Executor) -> ExecResult<Value> {
+    extreme_by(exec, Ordering::Less)
+}
+
+pub fn max_by(exec: &mut Executor) -> ExecResult<Value> {
+    extreme_by(exec, Ordering::Greater)
+}
+
+/// Finds the first element of a list whose key, as given by a one-argument function, compares to every other key in the given direction.
+fn extreme_by(exec: &mut Executor, direction: Ordering) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Bytecode(code, 1), Value::List(list)) = (val1, val2) {
+        let mut best: Option<(TinyInt, Value)> = None;
+        for item in list {
+            exec.stack.push(item.clone());
+            let key = match double_try!(exec.run_code_object(code.clone(), 1)) {
+                Value::Number(n) => n,
+                _ => return Ok(Err(ScriptError::ArgumentType)),
+            };
+            if best
+                .as_ref()
+                .is_none_or(|(best_key, _)| key.cmp(best_key) == direction)
+            {
+                best = Some((key, item));
+            }
+        }
+        Ok(best.map(|(_, item)| item).ok_or(ScriptError::ArgumentValue))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Sorts a list by the number a one-argument function returns for each element, calling it only once per element. The sort is stable, so elements with equal keys keep their order.
+pub fn sort_by_key(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Bytecode(code, 1), Value::List(list)) = (val1, val2) {
+        let mut keyed: Vec<(TinyInt, Value)> = Vec::with_capacity(list.len());
+        for item in list {
+            exec.stack.push(item.clone());
+            match double_try!(exec.run_code_object(code.clone(), 1)) {
+                Value::Number(key) => keyed.push((key, item)),
+                _ => return Ok(Err(ScriptError::ArgumentType)),
+            }
+        }
+        keyed.sort_by(|(key1, _), (key2, _)| key1.cmp(key2));
+        Ok(Ok(Value::List(
+            keyed.into_iter().map(|(_, item)| item).collect(),
+        )))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Splits a list into consecutive sublists of the given size, the last of which may be shorter.
+pub fn chunks(exec: &mut Executor) -> ExecResult<Value> {
+    sublists(exec, |list, size| {
+        list.chunks(size).map(<[Value]>::to_vec).collect()
+    })
+}
+
+/// Lists every run of consecutive elements of the given size, overlapping each other.
+pub fn windows(exec: &mut Executor) -> ExecResult<Value> {
+    sublists(exec, |list, size| {
+        list.windows(size).map(<[Value]>::to_vec).collect()
+    })
+}
+
+fn sublists(
+    exec: &mut Executor,
+    split: fn(&[Value], usize) -> Vec<Vec<Value>>,
+) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::List(list), Value::Number(n)) = (val1, val2) {
+        if n.is_negative() || n.is_zero() {
+            return Ok(Err(ScriptError::ArgumentValue));
+        }
+        // A size too big to fit behaves the same as one the length of the list.
+        let size = usize::try_from(n).unwrap_or(usize::MAX);
+        let parts = split(&list, size).into_iter().map(Value::List).collect();
+        Ok(Ok(Value::List(parts)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Lists the names of every builtin function, in the order they were added to the language.
+#[allow(clippy::unnecessary_wraps)]
+pub fn builtins(_exec: &mut Executor) -> ExecResult<Value> {
+    let names = INTRINSIC_IDENTS
+        .iter()
+        .map(|(name, _)| Value::Str((*name).to_owned()))
+        .collect();
+    Ok(Ok(Value::List(names)))
+}
+
+/// Describes the builtin with the given name and how many arguments it takes, or returns `None` if there is no such builtin.
+pub fn help(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Str(name) = exec.pop_stack()? {
+        let intrinsic = INTRINSIC_IDENTS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, intrinsic)| *intrinsic);
+        Ok(Ok(intrinsic.map_or(Value::None, |intrinsic| {
+            let arguments = match intrinsic.num_params() {
+                0 => "no arguments".to_owned(),
+                1 => "1 argument".to_owned(),
+                n => format!("{} arguments", n),
+            };
+            Value::Str(format!(
+                "`{}` takes {}. {}",
+                name,
+                arguments,
+                intrinsic.description()
+            ))
+        })))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the list with the value added to the front. Lists are currently stored as vectors, so this takes time proportional to the length of the list.
+pub fn cons(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let Value::List(mut list) = val2 {
+        list.insert(0, val1);
+        Ok(Ok(Value::List(list)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Folds a list or lazy sequence from the left, so that `fold_left(f init [a b])` is `f(f(init a) b)`.
+pub fn fold_left(exec: &mut Executor) -> ExecResult<Value> {
+    let list = exec.pop_stack()?;
+    let mut accum = exec.pop_stack()?;
+    if let (Value::Bytecode(code, 2), Some(mut elements)) = (exec.pop_stack()?, Elements::new(list))
+    {
+        while let Some(item) = double_try!(elements.next(exec)) {
+            exec.stack.push(accum);
+            exec.stack.push(item);
+            accum = double_try!(exec.run_code_object(code.clone(), 2));
+        }
+        Ok(Ok(accum))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Folds a list from the right, so that `fold_right(f init [a b])` is `f(a f(b init))`. Like with `fold`, a lazy sequence is read into a list first.
+pub fn fold_right(exec: &mut Executor) -> ExecResult<Value> {
+    let list = exec.pop_stack()?;
+    let mut accum = exec.pop_stack()?;
+    let code = match exec.pop_stack()? {
+        Value::Bytecode(code, 2) => code,
+        _ => return Ok(Err(ScriptError::ArgumentType)),
+    };
+    if let Some(list) = double_try!(read_all(exec, list)) {
+        for item in list.into_iter().rev() {
+            exec.stack.push(item);
+            exec.stack.push(accum);
+            accum = double_try!(exec.run_code_object(code.clone(), 2));
+        }
+        Ok(Ok(accum))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns a list of `n` elements, the first of which is the seed and each following one the result of calling the function on the one before it. The function is called one fewer times than there are elements, and an `n` that isn't positive gives an empty list.
+pub fn iterate(exec: &mut Executor) -> ExecResult<Value> {
+    let val3 = exec.pop_stack()?;
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Bytecode(code, 1), Value::Number(n)) = (val1, val3) {
+        let (n, mut list) = match constructed_list(exec, n) {
+            Ok(built) => built,
+            Err(e) => return Ok(Err(e)),
+        };
+        if n > 0 {
+            list.push(val2);
+        }
+        for _ in 1..n {
+            exec.stack.push(list[list.len() - 1].clone());
+            let next = double_try!(exec.run_code_object(code.clone(), 1));
+            list.push(next);
+        }
+        Ok(Ok(Value::List(list)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Adds up a list of numbers, giving `0` for an empty list.
+pub fn sum(exec: &mut Executor) -> ExecResult<Value> {
+    combine_numbers(exec, TinyInt::zero(), |x, y| x + y)
+}
+
+/// Multiplies a list of numbers, giving `1` for an empty list.
+pub fn product(exec: &mut Executor) -> ExecResult<Value> {
+    combine_numbers(exec, TinyInt::Inline(1), |x, y| x * y)
+}
+
+fn combine_numbers(
+    exec: &mut Executor,
+    identity: TinyInt,
+    op: fn(TinyInt, TinyInt) -> TinyInt,
+) -> ExecResult<Value> {
+    if let Some(mut elements) = Elements::new(exec.pop_stack()?) {
+        let mut accum = identity;
+        while let Some(item) = double_try!(elements.next(exec)) {
+            match item {
+                Value::Number(n) => accum = op(accum, n),
+                _ => return Ok(Err(ScriptError::ArgumentType)),
+            }
+        }
+        exec.check_overflow(Value::Number(accum))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Bounds a number to the inclusive range from `lo` to `hi`. It's an error for `lo` to be greater than `hi`.
+pub fn clamp(exec: &mut Executor) -> ExecResult<Value> {
+    let val3 = exec.pop_stack()?;
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Number(n), Value::Number(lo), Value::Number(hi)) = (val1, val2, val3) {
+        if lo > hi {
+            return Ok(Err(ScriptError::ArgumentValue));
+        }
+        Ok(Ok(Value::Number(n.clamp(lo, hi))))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+pub fn sign(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Number(n) = exec.pop_stack()? {
+        let sign = if n.is_negative() {
+            -1
+        } else if n.is_zero() {
+            0
+        } else {
+            1
+        };
+        Ok(Ok(Value::Number(TinyInt::Inline(sign))))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+pub fn take_while(exec: &mut Executor) -> ExecResult<Value> {
+    let (mut list, split) = double_try!(leading_run(exec));
+    list.truncate(split);
+    Ok(Ok(Value::List(list)))
+}
+
+pub fn drop_while(exec: &mut Executor) -> ExecResult<Value> {
+    let (mut list, split) = double_try!(leading_run(exec));
+    Ok(Ok(Value::List(list.split_off(split))))
+}
+
+/// Pops a predicate and a list, and finds how many elements at the front of the list satisfy the predicate. The predicate isn't called on any elements past the first that fails.
+fn leading_run(exec: &mut Executor) -> ExecResult<(Vec<Value>, usize)> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Bytecode(code, 1), Value::List(list)) = (val1, val2) {
+        for (i, item) in list.iter().enumerate() {
+            exec.stack.push(item.clone());
+            if !double_try!(exec.run_code_object(code.clone(), 1)).truthiness() {
+                return Ok(Ok((list, i)));
+            }
+        }
+        let len = list.len();
+        Ok(Ok((list, len)))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+pub fn default_value(exec: &mut Executor) -> ExecResult<Value> {
+    let fallback = exec.pop_stack()?;
+    match exec.pop_stack()? {
+        Value::None => Ok(Ok(fallback)),
+        value => Ok(Ok(value)),
+    }
+}
+
+/// Like `default`, but the fallback is a zero argument function that is only called if the value is `None`.
+pub fn default_with(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    match (val1, val2) {
+        (Value::None, Value::Bytecode(code, 0)) => exec.run_code_object(code, 0),
+        (value, Value::Bytecode(_, 0)) => Ok(Ok(value)),
+        _ => Ok(Err(ScriptError::ArgumentType)),
+    }
+}
+
+/// Tries each `[predicate handler]` pair in order, calling the handler with the value for the first predicate that returns a truthy value when called with it. If none match, this returns `None`. Both must be one argument functions.
+pub fn match_value(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    let patterns = match val2 {
+        Value::List(patterns) => patterns,
+        _ => return Ok(Err(ScriptError::ArgumentType)),
+    };
+    // Check every pattern up front so that a malformed one is caught even if an earlier one matches.
+    let mut arms = Vec::with_capacity(patterns.len());
+    for pattern in patterns {
+        match pattern {
+            Value::List(pair) => match <[Value; 2]>::try_from(pair) {
+                Ok([Value::Bytecode(predicate, 1), Value::Bytecode(handler, 1)]) => {
+                    arms.push((predicate, handler))
+                }
+                _ => return Ok(Err(ScriptError::ArgumentType)),
+            },
+            _ => return Ok(Err(ScriptError::ArgumentType)),
+        }
+    }
+    for (predicate, handler) in arms {
+        exec.stack.push(val1.clone());
+        if double_try!(exec.run_code_object(predicate, 1)).truthiness() {
+            exec.stack.push(val1);
+            return exec.run_code_object(handler, 1);
+        }
+    }
+    Ok(Ok(Value::None))
+}
+
+macro_rules! string_intrinsic {
+    ($self:ident, |$s:ident| $oper:expr) => {
+        pub fn $self(exec: &mut Executor) -> ExecResult<Value> {
+            if let Value::Str($s) = exec.pop_stack()? {
+                Ok(Ok(Value::from($oper)))
+            } else {
+                Ok(Err(ScriptError::ArgumentType))
+            }
+        }
+    };
+    ($self:ident, |$s:ident, $t:ident| $oper:expr) => {
+        pub fn $self(exec: &mut Executor) -> ExecResult<Value> {
+            let val2 = exec.pop_stack()?;
+            let val1 = exec.pop_stack()?;
+            if let (Value::Str($s), Value::Str($t)) = (val1, val2) {
+                Ok(Ok(Value::from($oper)))
+            } else {
+                Ok(Err(ScriptError::ArgumentType))
+            }
+        }
+    };
+}
+
+// Case conversion is unicode aware, so a character may turn into several, like `ß` into `SS`.
+string_intrinsic! {upper, |s| Value::Str(s.to_uppercase())}
+string_intrinsic! {lower, |s| Value::Str(s.to_lowercase())}
+string_intrinsic! {trim, |s| Value::Str(s.trim().to_owned())}
+string_intrinsic! {starts_with, |s, prefix| s.starts_with(&prefix)}
+string_intrinsic! {ends_with, |s, suffix| s.ends_with(&suffix)}
+
+pub fn replace(exec: &mut Executor) -> ExecResult<Value> {
+    let val3 = exec.pop_stack()?;
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Str(s), Value::Str(from), Value::Str(to)) = (val1, val2, val3) {
+        Ok(Ok(Value::Str(s.replace(&from, &to))))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the characters from the start index up to but not including the end index. Like with `insert`, indices count the positions between characters, so `-1` is the end of the string. Indices outside of the string, or a start past the end, are an error.
+pub fn substring(exec: &mut Executor) -> ExecResult<Value> {
+    let val3 = exec.pop_stack()?;
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Str(s), Value::Number(start), Value::Number(end)) = (val1, val2, val3) {
+        let len = s.chars().count();
+        match (resolve_index(start, len + 1), resolve_index(end, len + 1)) {
+            (Some(start), Some(end)) if start <= end => Ok(Ok(Value::Str(
+                s.chars().skip(start).take(end - start).collect(),
+            ))),
+            _ => Ok(Err(ScriptError::ArgumentValue)),
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the index of the first match counted in characters rather than bytes, so that it can be passed to `substring`.
+pub fn find(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Str(s), Value::Str(needle)) = (val1, val2) {
+        Ok(Ok(s.find(&needle).map_or(Value::None, |offset| {
+            Value::Number(s[..offset].chars().count().into())
+        })))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns a lazy sequence of the numbers from the start up to but not including the end, which is empty if the end isn't past the start.
+pub fn range(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Number(start), Value::Number(end)) = (val1, val2) {
+        Ok(Ok(Value::Lazy(Rc::new(Lazy::Range(start, end)))))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Reads every element of a lazy sequence into a list. A list is returned as it is.
+pub fn collect(exec: &mut Executor) -> ExecResult<Value> {
+    let val = exec.pop_stack()?;
+    match double_try!(read_all(exec, val)) {
+        Some(list) => Ok(Ok(Value::List(list))),
+        None => Ok(Err(ScriptError::ArgumentType)),
+    }
+}
+
+/// Calls the function on each element of a list or lazy sequence in order, for its side effects.
+pub fn each(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    if let (Value::Bytecode(code, 1), Some(mut elements)) = (val1, Elements::new(val2)) {
+        while let Some(item) = double_try!(elements.next(exec)) {
+            exec.stack.push(item);
+            double_try!(exec.run_code_object(code.clone(), 1));
+        }
+        Ok(Ok(Value::None))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Reads every element of a list or lazy sequence, or returns `None` for any other value. Since the whole sequence ends up in memory, this stops as soon as it grows past the executor's list limit.
+fn read_all(exec: &mut Executor, value: Value) -> ExecResult<Option<Vec<Value>>> {
+    let mut cursor = match value {
+        Value::List(list) => return Ok(Ok(Some(list))),
+        Value::Lazy(lazy) => Cursor::new(&lazy),
+        _ => return Ok(Ok(None)),
+    };
+    let mut list = vec![];
+    while let Some(item) = double_try!(cursor.next(exec)) {
+        if exec
+            .globals
+            .list_limit
+            .is_some_and(|limit| list.len() >= limit)
+        {
+            return Ok(Err(ScriptError::MemoryLimit));
+        }
+        list.push(item);
+    }
+    Ok(Ok(Some(list)))
+}
+
+/// The elements of either a list or a lazy sequence, for builtins that read them in order.
+enum Elements {
+    List(std::vec::IntoIter<Value>),
+    Lazy(Cursor),
+}
+
+impl Elements {
+    fn new(value: Value) -> Option<Self> {
+        match value {
+            Value::List(list) => Some(Self::List(list.into_iter())),
+            Value::Lazy(lazy) => Some(Self::Lazy(Cursor::new(&lazy))),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self, exec: &mut Executor) -> ExecResult<Option<Value>> {
+        match self {
+            Self::List(iter) => Ok(Ok(iter.next())),
+            Self::Lazy(cursor) => cursor.next(exec),
+        }
+    }
+}
+
+/// The position partway through reading a lazy sequence.
+enum Cursor {
+    Range(TinyInt, TinyInt),
+    Map(Rc<Code>, Box<Cursor>),
+    Filter(Rc<Code>, Box<Cursor>),
+}
+
+impl Cursor {
+    fn new(lazy: &Lazy) -> Self {
+        match lazy {
+            Lazy::Range(start, end) => Self::Range(start.clone(), end.clone()),
+            Lazy::Map(code, inner) => Self::Map(code.clone(), Box::new(Self::new(inner))),
+            Lazy::Filter(code, inner) => Self::Filter(code.clone(), Box::new(Self::new(inner))),
+        }
+    }
+
+    /// Works out the next element, or returns `None` once there are no more.
+    fn next(&mut self, exec: &mut Executor) -> ExecResult<Option<Value>> {
+        match self {
+            Self::Range(next, end) => {
+                if next >= end {
+                    return Ok(Ok(None));
+                }
+                let n = next.clone();
+                *next = n.clone() + TinyInt::Inline(1);
+                Ok(Ok(Some(Value::Number(n))))
+            }
+            Self::Map(code, inner) => match double_try!(inner.next(exec)) {
+                Some(item) => {
+                    exec.stack.push(item);
+                    let mapped_item = double_try!(exec.run_code_object(code.clone(), 1));
+                    Ok(Ok(Some(mapped_item)))
+                }
+                None => Ok(Ok(None)),
+            },
+            Self::Filter(code, inner) => {
+                while let Some(item) = double_try!(inner.next(exec)) {
+                    exec.stack.push(item.clone());
+                    if double_try!(exec.run_code_object(code.clone(), 1)).truthiness() {
+                        return Ok(Ok(Some(item)));
+                    }
+                }
+                Ok(Ok(None))
+            }
+        }
+    }
+}
+
+/// Returns the logical negation of the value's truthiness.
+pub fn not(exec: &mut Executor) -> ExecResult<Value> {
+    let val = exec.pop_stack()?;
+    Ok(Ok((!val.truthiness()).into()))
+}
+
+pub fn chars(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Str(s) = exec.pop_stack()? {
+        Ok(Ok(Value::List(
+            s.chars().map(|c| Value::Str(c.into())).collect(),
+        )))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the code point of a character. It's an error for the string to be empty or longer than one character.
+pub fn ord(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Str(s) = exec.pop_stack()? {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Ok(Value::Number(TinyInt::Inline(c as isize)))),
+            _ => Ok(Err(ScriptError::ArgumentValue)),
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Returns the character with the code point. It's an error for the number not to be a valid code point, such as a surrogate.
+pub fn chr(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Number(n) = exec.pop_stack()? {
+        match usize::try_from(n)
+            .ok()
+            .and_then(|n| char::from_u32(u32::try_from(n).ok()?))
+        {
+            Some(c) => Ok(Ok(Value::Str(c.into()))),
+            None => Ok(Err(ScriptError::ArgumentValue)),
+        }
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Calls the function on every value that isn't a list, however deeply it's nested, and returns a structure of the same shape with the results in their place. A value that isn't a list is passed to the function directly. This works through an explicit stack rather than recursion, so arbitrarily deep lists can't overflow the call stack.
+pub fn deep_map(exec: &mut Executor) -> ExecResult<Value> {
+    let val2 = exec.pop_stack()?;
+    let val1 = exec.pop_stack()?;
+    let code = match val1 {
+        Value::Bytecode(code, 1) => code,
+        _ => return Ok(Err(ScriptError::ArgumentType)),
+    };
+    // The elements left to visit and the results so far of each list we are partway through.
+    let mut levels: Vec<(std::vec::IntoIter<Value>, Vec<Value>)> = vec![];
+    let mut next = val2;
+    loop {
+        let mut result = match next {
+            Value::List(list) => {
+                levels.push((list.into_iter(), Vec::new()));
+                None
+            }
+            leaf => {
+                exec.stack.push(leaf);
+                Some(double_try!(exec.run_code_object(code.clone(), 1)))
+            }
+        };
+        // Finish every list that has run out of elements, until one has an element left to visit.
+        next = loop {
+            let (items, results) = match levels.last_mut() {
+                Some(level) => level,
+                None => return Ok(Ok(result.unwrap_or(Value::None))),
+            };
+            results.extend(result.take());
+            if let Some(item) = items.next() {
+                break item;
+            }
+            let (_, results) = levels.pop().expect("there is a level");
+            result = Some(Value::List(results));
+        };
+    }
+}
+
+/// Raises an error carrying both values if they aren't equal, in the sense of the [`PartialEq`] implementation of [`Value`].
+pub fn assert_eq(exec: &mut Executor) -> ExecResult<Value> {
+    let expected = exec.pop_stack()?;
+    let actual = exec.pop_stack()?;
+    if actual == expected {
+        Ok(Ok(Value::None))
+    } else {
+        Ok(Err(ScriptError::AssertionFailed {
+            actual: Box::new(actual),
+            expected: Box::new(expected),
+        }))
+    }
+}
+
+/// Runs the body, then always runs the cleanup, even if the body raised an error. The error from the body is raised again once the cleanup finishes, unless the cleanup raises its own error, which takes its place. Otherwise, the result is that of the body.
+pub fn with_cleanup(exec: &mut Executor) -> ExecResult<Value> {
+    let cleanup = exec.pop_stack()?;
+    let body = exec.pop_stack()?;
+    match (body, cleanup) {
+        (Value::Bytecode(body, 0), Value::Bytecode(cleanup, 0)) => {
+            let result = exec.run_code_object(body, 0)?;
+            // Keep the traceback of the body's error, rather than one left by the cleanup.
+            let traceback = exec.globals.traceback.take();
+            double_try!(exec.run_code_object(cleanup, 0));
+            exec.globals.traceback = traceback;
+            Ok(result)
+        }
+        _ => Ok(Err(ScriptError::ArgumentType)),
+    }
+}
+
+/// Reads a string written like a number literal, with an optional leading `-` and `_` allowed after any digit, returning `None` if it isn't one. There is no float type, so strings with a decimal point or exponent such as `4.2` or `1e3` aren't numbers either.
+pub fn to_number(exec: &mut Executor) -> ExecResult<Value> {
+    match exec.pop_stack()? {
+        Value::Str(s) => {
+            let digits = s.strip_prefix('-').unwrap_or(&s);
+            let is_number = digits.starts_with(|c: char| c.is_ascii_digit())
+                && digits.chars().all(|c| c.is_ascii_digit() || c == '_');
+            if !is_number {
+                return Ok(Ok(Value::None));
+            }
+            let n: BigInt = s
+                .replace('_', "")
+                .parse()
+                .expect("digits should parse as a number");
+            exec.check_overflow(Value::Number(n.into()))
+        }
+        _ => Ok(Err(ScriptError::ArgumentType)),
+    }
+}
+
+/// Returns the bytes of a string's UTF-8 encoding as a list of numbers. Unlike `chars`, a character outside of ASCII becomes several elements.
+pub fn to_bytes(exec: &mut Executor) -> ExecResult<Value> {
+    if let Value::Str(s) = exec.pop_stack()? {
+        Ok(Ok(Value::List(
+            s.bytes()
+                .map(|b| Value::Number(isize::from(b).into()))
+                .collect(),
+        )))
+    } else {
+        Ok(Err(ScriptError::ArgumentType))
+    }
+}
+
+/// Builds a string from a list of the bytes in its UTF-8 encoding, undoing `to_bytes`. It's an error for a number not to be a byte from 0 to 255, or for the bytes not to be valid UTF-8.
+pub fn from_bytes(exec: &mut Executor) -> ExecResult<Value> {
+    let list = match exec.pop_stack()? {
+        Value::List(list) => list,
+        _ => return Ok(Err(ScriptError::ArgumentType)),
+    };
+    let mut bytes = Vec::with_capacity(list.len());
+    for value in list {
+        match value {
+            Value::Number(n) => match usize::try_from(n).ok().and_then(|n| u8::try_from(n).ok()) {
+                Some(byte) => bytes.push(byte),
+                None => return Ok(Err(ScriptError::ArgumentValue)),
+            },
+            _ => return Ok(Err(ScriptError::ArgumentType)),
+        }
+    }
+    Ok(String::from_utf8(bytes)
+        .map(Value::Str)
+        .map_err(|_| ScriptError::ArgumentValue))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{eval, executor, result_of};
+    use super::super::NumericMode;
+    use super::{ScriptError, Value};
+
+    #[test]
+    fn args_empty_by_default() {
+        assert_eq!(eval("result := args()").unwrap().to_string(), "[]");
+    }
+
+    #[test]
+    fn args_injected() {
+        let exec =
+            executor("result := args()").with_args(vec!["foo".to_owned(), "bar baz".to_owned()]);
+        assert_eq!(
+            result_of(exec).unwrap().to_string(),
+            r#"["foo", "bar baz"]"#
+        );
+    }
+
+    #[test]
+    fn env_requires_system_access() {
+        assert!(matches!(
+            eval(r#"result := env("PATH")"#),
+            Err(ScriptError::AccessDenied)
+        ));
+    }
+
+    #[test]
+    fn env_lookup() {
+        std::env::set_var("BLING_TEST_ENV", "hello");
+        let exec = executor(
+            r#"result := push(push(list() env("BLING_TEST_ENV")) env("BLING_TEST_UNSET"))"#,
+        );
+        let result = result_of(exec.with_system_access(true));
+        assert_eq!(result.unwrap().to_string(), r#"["hello", None]"#);
+    }
+
+    #[test]
+    fn exit_unwinds_with_clamped_code() {
+        assert!(matches!(
+            eval("exit(3) result := 1"),
+            Err(ScriptError::Exit(3))
+        ));
+        assert!(matches!(eval("exit(-1)"), Err(ScriptError::Exit(255))));
+        assert!(matches!(eval("exit(256)"), Err(ScriptError::Exit(255))));
+        assert!(matches!(eval("exit(0)"), Err(ScriptError::Exit(0))));
+        assert!(matches!(
+            eval("exit(99999999999999999999999)"),
+            Err(ScriptError::Exit(255))
+        ));
+    }
+
+    #[test]
+    fn clock_is_monotonic() {
+        let result = eval("start := clock() result := sub(clock() start)").unwrap();
+        assert!(matches!(result, Value::Number(n) if !n.is_negative()));
+    }
+
+    #[test]
+    fn seeded_random_is_deterministic() {
+        let source = "
+            seed(1234)
+            result := list()
+            i := 0
+            while(() => sub(i 20) () => {
+                result = push(push(result random(10)) random(99999999999999999999999))
+                i = add(i 1)
+            })
+        ";
+        let first = eval(source).unwrap().to_string();
+        assert_eq!(first, eval(source).unwrap().to_string());
+        assert_ne!(
+            first,
+            eval(&source.replace("1234", "4321")).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn random_stays_in_range() {
+        let result = eval(
+            "result := list() while(() => sub(len(result) 100) () => result = push(result random(3)))",
+        );
+        if let Value::List(list) = result.unwrap() {
+            assert!(list.iter().all(|x| matches!(
+                x,
+                Value::Number(n) if !n.is_negative() && usize::try_from(n.clone()).unwrap() < 3
+            )));
+        } else {
+            panic!("expected a list");
+        }
+        assert!(matches!(
+            eval("result := random(0)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+    }
+
+    /// Builds a list of the numbers `0..n` in Bling source.
+    fn range_source(n: usize) -> String {
+        format!("nums := list() while(() => sub(len(nums) {n}) () => nums = push(nums len(nums)))")
+    }
+
+    fn sorted_numbers(value: Value) -> Vec<usize> {
+        let mut nums: Vec<usize> = match value {
+            Value::List(list) => list
+                .into_iter()
+                .map(|x| match x {
+                    Value::Number(n) => n.try_into().unwrap(),
+                    _ => panic!("expected a number"),
+                })
+                .collect(),
+            _ => panic!("expected a list"),
+        };
+        nums.sort_unstable();
+        nums
+    }
+
+    #[test]
+    fn shuffle_permutes() {
+        let source = format!("seed(7) {} result := shuffle(nums)", range_source(50));
+        let shuffled = eval(&source).unwrap();
+        assert_eq!(shuffled.to_string(), eval(&source).unwrap().to_string());
+        assert_ne!(
+            shuffled.to_string(),
+            eval(&range_source(50).replace("nums", "result"))
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(sorted_numbers(shuffled), (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sample_picks_distinct_elements() {
+        let source = format!("seed(7) {} result := sample(nums 10)", range_source(50));
+        let mut sampled = sorted_numbers(eval(&source).unwrap());
+        assert_eq!(sampled.len(), 10);
+        sampled.dedup();
+        assert_eq!(sampled.len(), 10);
+        let all = format!("{} result := sample(nums 50)", range_source(50));
+        assert_eq!(
+            sorted_numbers(eval(&all).unwrap()),
+            (0..50).collect::<Vec<_>>()
+        );
+        let too_many = format!("{} result := sample(nums 51)", range_source(50));
+        assert!(matches!(eval(&too_many), Err(ScriptError::ArgumentValue)));
+    }
+
+    #[test]
+    fn unique_keeps_first_occurrences() {
+        let result = eval("result := unique(push(push(push(push(push(list() 3) 1) 3) 2) 1))");
+        assert_eq!(result.unwrap().to_string(), "[3, 1, 2]");
+        let mixed = r#"
+            pair := push(push(list() 1) "a")
+            result := unique(push(push(push(push(push(list() "a") pair) 1) pair) "a"))
+        "#;
+        assert_eq!(eval(mixed).unwrap().to_string(), r#"["a", [1, "a"], 1]"#);
+    }
+
+    #[test]
+    fn group_by_parity() {
+        let source = format!(
+            "{} result := group_by((x) => mod(x 2) nums)",
+            range_source(6)
+        );
+        assert_eq!(
+            eval(&source).unwrap().to_string(),
+            "[[0, [0, 2, 4]], [1, [1, 3, 5]]]"
+        );
+        let bad_key = format!("{} result := group_by((x) => list() nums)", range_source(6));
+        assert!(matches!(eval(&bad_key), Err(ScriptError::ArgumentType)));
+    }
+
+    #[test]
+    fn partition_evens() {
+        let source = "
+            is_even := (x) => sub(1 mod(x 2))
+            result := partition(is_even push(push(push(push(list() 1) 2) 3) 4))
+        ";
+        assert_eq!(eval(source).unwrap().to_string(), "[[2, 4], [1, 3]]");
+        let source = format!(
+            "{} result := partition((x) => mod(x 3) nums)",
+            range_source(20)
+        );
+        let halves = match eval(&source).unwrap() {
+            Value::List(halves) => halves,
+            _ => panic!("expected a list"),
+        };
+        let mut joined = vec![];
+        for half in halves {
+            match half {
+                Value::List(half) => joined.extend(half),
+                _ => panic!("expected a list"),
+            }
+        }
+        assert_eq!(
+            sorted_numbers(Value::List(joined)),
+            (0..20).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn zip_with_sums_elementwise() {
+        let source = format!(
+            "{} result := zip_with((a b) => add(a b) nums push(push(list() 10) 20))",
+            range_source(3)
+        );
+        assert_eq!(eval(&source).unwrap().to_string(), "[10, 21]");
+        assert_eq!(
+            eval("result := zip_with").unwrap().to_string(),
+            "<builtin function zip_with>"
+        );
+    }
+
+    #[test]
+    fn zip_n_stops_at_shortest() {
+        let source = r#"
+            a := push(push(push(list() 1) 2) 3)
+            b := push(push(list() "x") "y")
+            c := push(push(push(push(list() 7) 8) 9) 10)
+            result := zip_n(push(push(push(list() a) b) c))
+        "#;
+        assert_eq!(
+            eval(source).unwrap().to_string(),
+            r#"[[1, "x", 7], [2, "y", 8]]"#
+        );
+        assert_eq!(eval("result := zip_n(list())").unwrap().to_string(), "[]");
+        assert!(matches!(
+            eval("result := zip_n(push(list() 5))"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn insert_at_positions() {
+        let insert_at = |index: &str| {
+            let source = format!("{} result := insert(nums {} 9)", range_source(3), index);
+            eval(&source).map(|v| v.to_string())
+        };
+        assert_eq!(insert_at("0").unwrap(), "[9, 0, 1, 2]");
+        assert_eq!(insert_at("1").unwrap(), "[0, 9, 1, 2]");
+        assert_eq!(insert_at("3").unwrap(), "[0, 1, 2, 9]");
+        assert_eq!(insert_at("-1").unwrap(), "[0, 1, 2, 9]");
+        assert_eq!(insert_at("-4").unwrap(), "[9, 0, 1, 2]");
+        assert!(matches!(insert_at("4"), Err(ScriptError::ArgumentValue)));
+        assert!(matches!(insert_at("-5"), Err(ScriptError::ArgumentValue)));
+    }
+
+    #[test]
+    fn remove_at_positions() {
+        let remove_at = |index: &str| {
+            let source = format!("{} result := remove(nums {})", range_source(3), index);
+            eval(&source).map(|v| v.to_string())
+        };
+        assert_eq!(remove_at("0").unwrap(), "[1, 2]");
+        assert_eq!(remove_at("1").unwrap(), "[0, 2]");
+        assert_eq!(remove_at("2").unwrap(), "[0, 1]");
+        assert_eq!(remove_at("-1").unwrap(), "[0, 1]");
+        assert_eq!(remove_at("-3").unwrap(), "[1, 2]");
+        assert!(matches!(remove_at("3"), Err(ScriptError::ArgumentValue)));
+        assert!(matches!(remove_at("-4"), Err(ScriptError::ArgumentValue)));
+    }
+
+    #[test]
+    fn set_and_update_at() {
+        let with_nums = |call: &str| {
+            let source = format!("{} result := {}", range_source(3), call);
+            eval(&source).map(|v| v.to_string())
+        };
+        assert_eq!(with_nums("set_at(nums 0 9)").unwrap(), "[9, 1, 2]");
+        assert_eq!(with_nums("set_at(nums -1 9)").unwrap(), "[0, 1, 9]");
+        assert_eq!(
+            with_nums("update_at(nums -2 (x) => add(x 10))").unwrap(),
+            "[0, 11, 2]"
+        );
+        assert!(matches!(
+            with_nums("set_at(nums 3 9)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            with_nums("update_at(nums -4 (x) => x)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+    }
+
+    #[test]
+    fn replicate_lengths() {
+        assert_eq!(
+            eval(r#"result := replicate(3 "ab")"#).unwrap().to_string(),
+            r#"["ab", "ab", "ab"]"#
+        );
+        assert_eq!(eval("result := replicate(0 1)").unwrap().to_string(), "[]");
+        assert_eq!(eval("result := replicate(-5 1)").unwrap().to_string(), "[]");
+        assert!(matches!(
+            eval("result := replicate(1000000000000 1)"),
+            Err(ScriptError::MemoryLimit)
+        ));
+        assert!(matches!(
+            eval("result := replicate(100000000000000000000000000 1)"),
+            Err(ScriptError::MemoryLimit)
+        ));
+    }
+
+    #[test]
+    fn seq_runs_in_order() {
+        let thunks = "log := list()
+            thunks := push(push(push(list()
+                () => { log = push(log 1) 10 })
+                () => { log = push(log 2) 20 })
+                () => { log = push(log 3) 30 })";
+        let value = eval(&format!(
+            "{} out := seq(thunks) result := push(log out)",
+            thunks
+        ));
+        assert_eq!(value.unwrap().to_string(), "[1, 2, 3, 30]");
+        assert_eq!(eval("result := seq(list())").unwrap().to_string(), "None");
+        let value = eval(&format!("{} seq(push(thunks 5)) result := 0", thunks));
+        assert!(matches!(value, Err(ScriptError::ArgumentType)));
+        let value = eval(&format!(
+            "{} r := seq(push(thunks (x) => x)) result := log",
+            thunks
+        ));
+        assert!(matches!(value, Err(ScriptError::ArgumentType)));
+    }
+
+    #[test]
+    fn memoize_caches_by_arguments() {
+        let source = "calls := 0
+            square := memoize((n) => {
+                calls = add(calls 1)
+                mul(n n)
+            })";
+        let value = eval(&format!(
+            "{} a := square(3) b := square(3) c := square(4) d := square(3) result := push(push(push(list() calls) a) c)",
+            source
+        ));
+        assert_eq!(value.unwrap().to_string(), "[2, 9, 16]");
+        // Copies of a memoized function share its cache.
+        let value = eval(&format!(
+            "{} copy := square copy(5) square(5) result := calls",
+            source
+        ));
+        assert_eq!(value.unwrap().to_string(), "1");
+        let source = "calls := 0
+            count := memoize((f) => {
+                calls = add(calls 1)
+                f
+            })
+            count(() => 1) count(() => 1) count(print) count(print)
+            result := calls";
+        assert_eq!(eval(source).unwrap().to_string(), "3");
+        let value = eval("f := memoize((a b = 2 rest...) => push(push(rest a) b)) result := push(push(list() f(1)) f(1 3 4))");
+        assert_eq!(value.unwrap().to_string(), "[[1, 2], [4, 1, 3]]");
+        assert!(matches!(
+            eval("result := memoize(5)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn type_predicates() {
+        let values = "values := push(push(push(push(push(list() {}) 1) \"s\") list()) print)";
+        let check = |predicate: &str| {
+            eval(&format!(
+                "{} result := map((x) => {}(x) values)",
+                values, predicate
+            ))
+            .unwrap()
+            .to_string()
+        };
+        assert_eq!(check("is_none"), "[1, 0, 0, 0, 0]");
+        assert_eq!(check("is_number"), "[0, 1, 0, 0, 0]");
+        assert_eq!(check("is_string"), "[0, 0, 1, 0, 0]");
+        assert_eq!(check("is_list"), "[0, 0, 0, 1, 0]");
+        assert_eq!(check("is_callable"), "[0, 0, 0, 0, 1]");
+        assert_eq!(
+            eval("result := is_callable((x) => x)").unwrap().to_string(),
+            "1"
+        );
+    }
+
+    #[test]
+    fn structural_equality() {
+        let check = |expr: &str| eval(&format!("result := {}", expr)).unwrap().to_string();
+        assert_eq!(check("eq(1 1)"), "1");
+        assert_eq!(check("eq(1 2)"), "0");
+        assert_eq!(check(r#"eq("a" "a")"#), "1");
+        assert_eq!(check(r#"eq("1" 1)"#), "0");
+        assert_eq!(check("eq({} {})"), "1");
+        assert_eq!(check("eq(print print)"), "1");
+        assert_eq!(check("eq(print len)"), "0");
+        // Functions are equal only to themselves, and each closure is a different function.
+        assert_eq!(check("{ f := (x) => x g := f eq(f g) }"), "1");
+        assert_eq!(check("{ n := 1 f := () => n g := () => n eq(f g) }"), "0");
+        // Identical lambdas that capture nothing share one constant, so they are the same function.
+        assert_eq!(check("{ f := () => 1 g := () => 1 eq(f g) }"), "1");
+        assert_eq!(check("{ f := () => 1 g := () => 2 eq(f g) }"), "0");
+        assert_eq!(check("{ f := memoize((x) => x) eq(f f) }"), "1");
+        assert_eq!(check("{ f := (x) => x eq(f memoize(f)) }"), "0");
+        assert_eq!(
+            check("{ adder := (n) => (x) => add(x n) eq(adder(1) adder(1)) }"),
+            "0"
+        );
+        // A number that passed through a heap allocation is still equal to the same inline number.
+        let big = "9223372036854775807";
+        let roundtrip = format!("sub(add({} 1) 1)", big);
+        assert_eq!(check(&format!("eq({} {})", big, roundtrip)), "1");
+        assert_eq!(
+            check(&format!(
+                "eq(push(push(list() 1) {}) push(push(list() 1) {}))",
+                big, roundtrip
+            )),
+            "1"
+        );
+        assert_eq!(
+            check(&format!("contains(push(list() {}) {})", roundtrip, big)),
+            "1"
+        );
+        assert_eq!(check("contains(push(list() 1) 2)"), "0");
+        assert_eq!(check("eq(push(list() 1) push(push(list() 1) 1))"), "0");
+    }
+
+    #[test]
+    fn do_while_runs_body_first() {
+        let value = eval("n := 0 result := do_while(=> { n = add(n 1) n } => 0)");
+        assert_eq!(value.unwrap().to_string(), "1");
+        let value = eval("n := 0 do_while(=> { n = add(n 1) } => sub(3 n)) result := n");
+        assert_eq!(value.unwrap().to_string(), "3");
+        let value = eval("n := 0 while(=> 0 => { n = add(n 1) }) result := n");
+        assert_eq!(value.unwrap().to_string(), "0");
+        assert!(matches!(
+            eval("result := do_while(1 => 0)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn loop_until_break() {
+        let when = "when := (cond body) => map((x) => body() filter((x) => x push(list() cond)))";
+        // `break` can be called from within nested functions and builtins.
+        let value = eval(&format!(
+            "{} n := 0 out := loop(=> {{ n = add(n 1) when(eq(n 5) () => break(mul(n 10))) }}) result := push(push(list() out) n)",
+            when
+        ));
+        assert_eq!(value.unwrap().to_string(), "[50, 5]");
+        let value = eval("result := loop(=> break(add(loop(=> break(7)) 1)))");
+        assert_eq!(value.unwrap().to_string(), "8");
+        assert!(matches!(
+            eval("result := break(1)"),
+            Err(ScriptError::Break(_))
+        ));
+        assert!(matches!(
+            eval("result := loop(5)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn radix_conversion() {
+        assert_eq!(
+            eval("result := to_radix(-255 16)").unwrap().to_string(),
+            "-ff"
+        );
+        assert_eq!(eval("result := to_radix(5 2)").unwrap().to_string(), "101");
+        assert_eq!(eval("result := to_radix(0 36)").unwrap().to_string(), "0");
+        // 2^80 is too big to be stored inline.
+        let value =
+            eval("big := fold((a b) => mul(a b) replicate(80 2)) result := to_radix(big 16)");
+        assert_eq!(value.unwrap().to_string(), format!("1{}", "0".repeat(20)));
+        assert!(matches!(
+            eval("result := to_radix(5 37)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval("result := to_radix(5 1)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval("result := to_radix(\"5\" 10)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn longest_and_shortest_sublists() {
+        let lists =
+            "lists := push(push(push(list() list()) push(push(list() 1) 2)) push(list() 3))";
+        let value = eval(&format!("{} result := max_by((l) => len(l) lists)", lists));
+        assert_eq!(value.unwrap().to_string(), "[1, 2]");
+        let value = eval(&format!("{} result := min_by((l) => len(l) lists)", lists));
+        assert_eq!(value.unwrap().to_string(), "[]");
+        // Ties go to the earliest element.
+        let value = eval("result := max_by((x) => 0 push(push(list() 1) 2))");
+        assert_eq!(value.unwrap().to_string(), "1");
+        assert!(matches!(
+            eval("result := min_by((x) => x list())"),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval("result := min_by((x) => \"a\" push(list() 1))"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn chunks_and_windows() {
+        let five = "nums := push(push(push(push(push(list() 1) 2) 3) 4) 5)";
+        let value = eval(&format!("{} result := chunks(nums 2)", five));
+        assert_eq!(value.unwrap().to_string(), "[[1, 2], [3, 4], [5]]");
+        let value = eval(&format!("{} result := windows(nums 3)", five));
+        assert_eq!(
+            value.unwrap().to_string(),
+            "[[1, 2, 3], [2, 3, 4], [3, 4, 5]]"
+        );
+        let value = eval(&format!("{} result := windows(nums 6)", five));
+        assert_eq!(value.unwrap().to_string(), "[]");
+        let value = eval(&format!("{} result := chunks(nums 9)", five));
+        assert_eq!(value.unwrap().to_string(), "[[1, 2, 3, 4, 5]]");
+        assert!(matches!(
+            eval("result := chunks(list() 0)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval("result := windows(list() -1)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+    }
+
+    #[test]
+    fn builtins_are_listed() {
+        let value = eval("result := builtins()").unwrap();
+        let names = match value {
+            Value::List(names) => names,
+            _ => panic!("`builtins` should return a list"),
+        };
+        assert_eq!(names.len(), super::INTRINSIC_IDENTS.len());
+        assert!(names.contains(&Value::Str("builtins".to_owned())));
+        assert!(eval("result := contains(builtins() \"print\")")
+            .unwrap()
+            .truthiness());
+    }
+
+    #[test]
+    fn help_describes_builtins() {
+        let value = eval("result := help(\"add\")").unwrap();
+        assert_eq!(
+            value.to_string(),
+            "`add` takes 2 arguments. Adds two numbers."
+        );
+        let value = eval("result := help(\"list\")").unwrap();
+        assert!(value.to_string().contains("no arguments"));
+        assert!(matches!(
+            eval("result := help(\"nonexistent\")"),
+            Ok(Value::None)
+        ));
+        assert!(matches!(
+            eval("result := help(5)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn cons_prepends() {
+        let value = eval("result := cons(1 cons(2 push(list() 3)))").unwrap();
+        assert_eq!(value.to_string(), "[1, 2, 3]");
+        assert_eq!(
+            eval("result := cons(list() list())").unwrap().to_string(),
+            "[[]]"
+        );
+        assert!(matches!(
+            eval("result := cons(1 2)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn fold_directions() {
+        let nums = "minus := (a b) => sub(a b) nums := push(push(push(list() 1) 2) 3)";
+        // ((0 - 1) - 2) - 3
+        let value = eval(&format!("{} result := fold_left(minus 0 nums)", nums));
+        assert_eq!(value.unwrap().to_string(), "-6");
+        // 1 - (2 - (3 - 0))
+        let value = eval(&format!("{} result := fold_right(minus 0 nums)", nums));
+        assert_eq!(value.unwrap().to_string(), "2");
+        // 1 - (2 - 3)
+        let value = eval(&format!("{} result := fold(minus nums)", nums));
+        assert_eq!(value.unwrap().to_string(), "2");
+        let value = eval("result := fold_left((a b) => a 5 list())");
+        assert_eq!(value.unwrap().to_string(), "5");
+        assert!(matches!(
+            eval("result := fold_right((a) => a 0 list())"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn iterate_powers_of_two() {
+        let value = eval("result := iterate((x) => mul(x 2) 1 6)").unwrap();
+        assert_eq!(value.to_string(), "[1, 2, 4, 8, 16, 32]");
+        let value = eval("result := iterate((x) => mul(x 2) 1 -3)").unwrap();
+        assert_eq!(value.to_string(), "[]");
+        // The function is never called for the seed alone.
+        let value = eval("result := iterate((x) => add(x list()) 1 1)").unwrap();
+        assert_eq!(value.to_string(), "[1]");
+        assert!(matches!(
+            eval("result := iterate((x) => x 1 1000000000000)"),
+            Err(ScriptError::MemoryLimit)
+        ));
+    }
+
+    #[test]
+    fn sum_and_product() {
+        let nums = "nums := iterate((x) => add(x 1) 1 25)";
+        assert_eq!(
+            eval(&format!("{} result := sum(nums)", nums))
+                .unwrap()
+                .to_string(),
+            "325"
+        );
+        // 25! is far too big for an `isize`, so the product has to be promoted along the way.
+        let value = eval(&format!("{} result := product(nums)", nums)).unwrap();
+        assert!(matches!(&value, Value::Number(super::TinyInt::Heap(_))));
+        assert_eq!(value.to_string(), "15511210043330985984000000");
+        assert_eq!(eval("result := sum(list())").unwrap().to_string(), "0");
+        assert_eq!(eval("result := product(list())").unwrap().to_string(), "1");
+        assert!(matches!(
+            eval("result := sum(push(list() \"1\"))"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn clamp_and_sign() {
+        let clamp = |args| eval(&format!("result := clamp({})", args)).map(|v| v.to_string());
+        assert_eq!(clamp("5 0 10").unwrap(), "5");
+        assert_eq!(clamp("-1 0 10").unwrap(), "0");
+        assert_eq!(clamp("11 0 10").unwrap(), "10");
+        assert_eq!(clamp("0 0 10").unwrap(), "0");
+        assert_eq!(clamp("10 0 10").unwrap(), "10");
+        assert_eq!(clamp("3 7 7").unwrap(), "7");
+        assert!(matches!(clamp("5 10 0"), Err(ScriptError::ArgumentValue)));
+        let sign = |n| eval(&format!("result := sign({})", n)).unwrap().to_string();
+        assert_eq!(sign("-99999999999999999999999"), "-1");
+        assert_eq!(sign("-1"), "-1");
+        assert_eq!(sign("0"), "0");
+        assert_eq!(sign("1"), "1");
+        assert_eq!(sign("99999999999999999999999"), "1");
+    }
+
+    #[test]
+    fn take_and_drop_while_even() {
+        let setup =
+            "even := (x) => eq(mod(x 2) 0) nums := cons(2 cons(4 cons(6 cons(1 push(list() 2)))))";
+        let value = eval(&format!("{} result := take_while(even nums)", setup));
+        assert_eq!(value.unwrap().to_string(), "[2, 4, 6]");
+        let value = eval(&format!("{} result := drop_while(even nums)", setup));
+        assert_eq!(value.unwrap().to_string(), "[1, 2]");
+        let value = eval(&format!("{} result := take_while((x) => 1 nums)", setup));
+        assert_eq!(value.unwrap().to_string(), "[2, 4, 6, 1, 2]");
+        let value = eval(&format!("{} result := drop_while((x) => 1 nums)", setup));
+        assert_eq!(value.unwrap().to_string(), "[]");
+        // Nothing past the first failure is checked.
+        let value = eval("result := take_while((x) => add(x 0) cons(0 push(list() \"a\")))");
+        assert_eq!(value.unwrap().to_string(), "[]");
+    }
+
+    #[test]
+    fn numeric_modes_at_the_boundary() {
+        let max = isize::MAX;
+        let run = |source: String, mode| result_of(executor(&source).with_numeric_mode(mode));
+        let value = run(format!("result := add({} 1)", max), NumericMode::BigInt);
+        assert_eq!(value.unwrap().to_string(), (max as i128 + 1).to_string());
+        let value = run(format!("result := add({} 0)", max), NumericMode::Checked);
+        assert_eq!(value.unwrap().to_string(), max.to_string());
+        for source in [
+            format!("result := add({} 1)", max),
+            format!("result := sub(-{} 2)", max),
+            format!("result := mul({} 2)", max),
+            format!("result := product(cons({} push(list() 2)))", max),
+            format!("x := sub(-{} 1) result := -x", max),
+        ] {
+            assert!(
+                matches!(
+                    run(source.clone(), NumericMode::Checked),
+                    Err(ScriptError::Overflow)
+                ),
+                "{} should overflow",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn defaults_for_none() {
+        assert_eq!(
+            eval("result := default(div(1 0) 7)").unwrap().to_string(),
+            "7"
+        );
+        assert_eq!(
+            eval("result := default(div(8 2) 7)").unwrap().to_string(),
+            "4"
+        );
+        // Falsy values other than `None` are kept.
+        assert_eq!(eval("result := default(0 7)").unwrap().to_string(), "0");
+        // The fallback only runs when it's needed.
+        let counted = "calls := 0 fallback := () => { calls = add(calls 1) 7 }";
+        let value = eval(&format!(
+            "{} x := default_with(div(1 0) fallback) result := push(push(list() x) calls)",
+            counted
+        ));
+        assert_eq!(value.unwrap().to_string(), "[7, 1]");
+        let value = eval(&format!(
+            "{} x := default_with(3 fallback) result := push(push(list() x) calls)",
+            counted
+        ));
+        assert_eq!(value.unwrap().to_string(), "[3, 0]");
+        assert!(matches!(
+            eval("result := default_with(3 7)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn match_on_list_shape() {
+        let setup = "
+            empty_arm := push(push(list() (l) => eq(l list())) (l) => \"empty\")
+            head_arm := push(push(list() (l) => is_list(l)) (l) => at(l 0))
+            arms := push(push(list() empty_arm) head_arm)
+        ";
+        let value = eval(&format!("{} result := match(list() arms)", setup));
+        assert_eq!(value.unwrap().to_string(), "empty");
+        let value = eval(&format!("{} result := match(push(list() 7) arms)", setup));
+        assert_eq!(value.unwrap().to_string(), "7");
+        let value = eval(&format!("{} result := match(5 arms)", setup));
+        assert!(matches!(value, Ok(Value::None)));
+        let value = eval(&format!("{} result := match(list() push(arms 5))", setup));
+        assert!(matches!(value, Err(ScriptError::ArgumentType)));
+    }
+
+    #[test]
+    fn write_and_flush() {
+        use std::cell::RefCell;
+        use std::io::{self, Write};
+        use std::rc::Rc;
+        /// Records where flushes happen with a `|`.
+        #[derive(Default)]
+        struct Recorder(Vec<u8>);
+        impl Write for Recorder {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.0.push(b'|');
+                Ok(())
+            }
+        }
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let exec = executor("write(\"a\") flush() write(1) write(2) print(\"b\") result := 0")
+            .with_output(recorder.clone());
+        result_of(exec).unwrap();
+        assert_eq!(String::from_utf8_lossy(&recorder.borrow().0), "a|12b\n|");
+    }
+
+    #[test]
+    fn len_of_strings() {
+        assert_eq!(
+            eval("result := len(\"héllo, 世界\")").unwrap().to_string(),
+            "9"
+        );
+        assert_eq!(eval("result := len(\"\")").unwrap().to_string(), "0");
+        assert_eq!(
+            eval("result := len(push(list() 1))").unwrap().to_string(),
+            "1"
+        );
+        assert!(matches!(
+            eval("result := len(5)"),
+            Err(ScriptError::ArgumentType)
+        ));
+        assert!(matches!(
+            eval("result := len({})"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn string_functions() {
+        let string = |source: &str| eval(&format!("result := {}", source)).unwrap().to_string();
+        assert_eq!(string(r#"upper("straße")"#), "STRASSE");
+        assert_eq!(string(r#"lower("ÀB")"#), "àb");
+        assert_eq!(string(r#"trim("  hi there \n")"#), "hi there");
+        assert_eq!(string(r#"starts_with("hello" "he")"#), "1");
+        assert_eq!(string(r#"starts_with("hello" "lo")"#), "0");
+        assert_eq!(string(r#"ends_with("hello" "lo")"#), "1");
+        assert_eq!(string(r#"ends_with("hello" "")"#), "1");
+        assert_eq!(string(r#"replace("a-b-c" "-" "+")"#), "a+b+c");
+        assert_eq!(string(r#"replace("aaa" "aa" "b")"#), "ba");
+        assert!(matches!(
+            eval("result := upper(5)"),
+            Err(ScriptError::ArgumentType)
+        ));
+        assert!(matches!(
+            eval(r#"result := replace("a" "a" list())"#),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn string_search() {
+        let string = |source: &str| eval(&format!("result := {}", source)).unwrap().to_string();
+        assert_eq!(string(r#"find("où est le café" "café")"#), "10");
+        assert_eq!(string(r#"find("où est le café" "thé")"#), "None");
+        assert_eq!(
+            eval(r#"s := "où est le café" result := substring(s find(s "café") -1)"#)
+                .unwrap()
+                .to_string(),
+            "café"
+        );
+        assert_eq!(string(r#"substring("naïve" 1 3)"#), "aï");
+        assert_eq!(string(r#"substring("naïve" 2 2)"#), "");
+        assert_eq!(string(r#"contains("naïve" "ïv")"#), "1");
+        assert_eq!(string(r#"contains("naïve" "iv")"#), "0");
+        assert_eq!(string("contains(push(push(list() 1) 2) 2)"), "1");
+        assert!(matches!(
+            eval(r#"result := substring("abc" 2 1)"#),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval(r#"result := substring("abc" 0 5)"#),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval(r#"result := contains("abc" 1)"#),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn lazy_sequences() {
+        let string = |source: &str| eval(&format!("result := {}", source)).unwrap().to_string();
+        assert_eq!(string("range(0 3)"), "<lazy sequence>");
+        assert_eq!(string("collect(range(2 6))"), "[2, 3, 4, 5]");
+        assert_eq!(string("collect(range(3 -1))"), "[]");
+        assert_eq!(
+            string("collect(filter((x) => mod(x 3) map((x) => mul(x x) range(0 7))))"),
+            "[1, 4, 16, 25]"
+        );
+        assert_eq!(string("sum(map((x) => mul(x 2) range(0 1000)))"), "999000");
+        assert_eq!(
+            string("fold_left((a x) => push(a x) list() range(0 3))"),
+            "[0, 1, 2]"
+        );
+        assert_eq!(
+            string("fold_right((x a) => push(a x) list() range(0 3))"),
+            "[2, 1, 0]"
+        );
+        assert_eq!(string("fold((x y) => sub(x y) range(1 4))"), "2");
+        let exec = result_of(executor(
+            "count := 0 each((x) => { count = add(count x) } range(0 5)) result := count",
+        ));
+        assert_eq!(exec.unwrap().to_string(), "10");
+        // Each reading starts over, so a sequence can be used more than once.
+        let result = eval("nums := range(0 4) result := push(push(list() sum(nums)) sum(nums))");
+        assert_eq!(result.unwrap().to_string(), "[6, 6]");
+        assert!(matches!(
+            eval("result := collect(5)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn not_negates_truthiness() {
+        let check = |expr: &str| {
+            eval(&format!("result := not({})", expr))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(check("{}"), "1");
+        assert_eq!(check("0"), "1");
+        assert_eq!(check("5"), "0");
+        assert_eq!(check("-5"), "0");
+        assert_eq!(check("100000000000000000000000"), "0");
+        assert_eq!(check(r#""""#), "1");
+        assert_eq!(check(r#""a""#), "0");
+        assert_eq!(check("list()"), "1");
+        assert_eq!(check("push(list() 0)"), "0");
+        assert_eq!(check("(x) => x"), "0");
+        assert_eq!(check("print"), "0");
+        assert_eq!(check("range(0 0)"), "0");
+        assert_eq!(check("not(5)"), "1");
+    }
+
+    #[test]
+    fn characters() {
+        let string = |source: &str| eval(&format!("result := {}", source)).unwrap().to_string();
+        assert_eq!(string(r#"chars("añb")"#), r#"["a", "ñ", "b"]"#);
+        assert_eq!(string(r#"chars("")"#), "[]");
+        assert_eq!(string(r#"at("añb" 1)"#), "ñ");
+        assert_eq!(string(r#"at("añb" -1)"#), "b");
+        assert_eq!(string(r#"at("añb" 3)"#), "None");
+        assert_eq!(string(r#"ord("ñ")"#), "241");
+        assert_eq!(string("chr(241)"), "ñ");
+        // Arithmetic on characters goes through their code points.
+        assert_eq!(string(r#"chr(add(ord("a") 1))"#), "b");
+        assert!(matches!(
+            eval(r#"result := ord("ab")"#),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval(r#"result := ord("")"#),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval("result := chr(55296)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval("result := chr(-1)"),
+            Err(ScriptError::ArgumentValue)
+        ));
+    }
+
+    #[test]
+    fn deep_map_keeps_shape() {
+        let result = eval(
+            "
+            inner := push(push(list() 3) list())
+            middle := push(push(list() 2) inner)
+            nested := push(push(push(list() 1) middle) push(list() 4))
+            result := deep_map((x) => mul(x 10) nested)
+        ",
+        );
+        assert_eq!(result.unwrap().to_string(), "[10, [20, [30, []]], [40]]");
+        assert_eq!(
+            eval("result := deep_map((x) => mul(x 10) 5)")
+                .unwrap()
+                .to_string(),
+            "50"
+        );
+        assert!(matches!(
+            eval("result := deep_map(5 list())"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn deep_map_on_deep_list() {
+        let mut exec = executor("result := (x) => add(x 1)");
+        exec.run().unwrap().unwrap();
+        let name_index = exec.globals.idents.get_index_of("result").unwrap();
+        let increment = exec.lookup_value(name_index).unwrap();
+        let mut list = Value::Number(super::TinyInt::zero());
+        for _ in 0..100_000 {
+            list = Value::List(vec![list]);
+        }
+        exec.stack.push(increment);
+        exec.stack.push(list);
+        let mut list = super::deep_map(&mut exec).unwrap().unwrap();
+        let mut depth = 0;
+        // Dropping the list recursively would overflow the stack, so take it apart by hand.
+        while let Value::List(mut inner) = list {
+            assert_eq!(inner.len(), 1);
+            list = inner.pop().unwrap();
+            depth += 1;
+        }
+        assert_eq!(depth, 100_000);
+        assert_eq!(list, Value::Number(super::TinyInt::Inline(1)));
+    }
+
+    #[test]
+    fn assert_eq_reports_both_values() {
+        let pair = r#"push(push(list() 1) "a")"#;
+        let result = eval(&format!("result := assert_eq({} {})", pair, pair));
+        assert_eq!(result.unwrap(), Value::None);
+        let error = eval(&format!("result := assert_eq({} push(list() 1))", pair)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"assertion failed: expected [1], got [1, "a"]"#
+        );
+        let error = eval(r#"result := assert_eq("1" 1)"#).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"assertion failed: expected 1, got "1""#
+        );
+    }
+
+    #[test]
+    fn once_runs_thunk_once() {
+        let result = eval(
+            "
+            calls := 0
+            f := once(=> { calls = add(calls 1) 42 })
+            first := f()
+            result := push(push(push(list() first) f()) f())
+            result = push(result calls)
+        ",
+        );
+        assert_eq!(result.unwrap().to_string(), "[42, 42, 42, 1]");
+        assert!(matches!(
+            eval("result := once((x) => x)"),
+            Err(ScriptError::ArgumentType)
+        ));
+        assert!(matches!(
+            eval("result := once((x = 1) => x)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn set_operations() {
+        let eval_with = |expr: &str| {
+            let source = format!(
+                "
+                list_of := (items...) => items
+                a := list_of(1 2 2 3 \"x\" list_of(4))
+                b := list_of(3 list_of(4) 5 1 5)
+                c := list_of(6 7)
+                result := {}
+            ",
+                expr
+            );
+            eval(&source).unwrap().to_string()
+        };
+        assert_eq!(eval_with("union(a b)"), r#"[1, 2, 3, "x", [4], 5]"#);
+        assert_eq!(eval_with("intersection(a b)"), "[1, 3, [4]]");
+        assert_eq!(eval_with("difference(a b)"), r#"[2, "x"]"#);
+        assert_eq!(eval_with("union(a c)"), r#"[1, 2, 3, "x", [4], 6, 7]"#);
+        assert_eq!(eval_with("intersection(a c)"), "[]");
+        assert_eq!(eval_with("difference(b c)"), "[3, [4], 5, 1]");
+        // Functions have no key, so they are compared by identity instead.
+        let source = "
+            f := (x) => x
+            g := (x) => x
+            result := intersection(push(push(push(list() f) g) 1) push(push(list() 1) g))
+        ";
+        let result = eval(source).unwrap();
+        assert!(matches!(&result, Value::List(list) if list.len() == 2));
+        assert!(matches!(
+            eval("result := union(list() 1)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn transpose_grid() {
+        let eval_with = |expr: &str| {
+            let source = format!(
+                "
+                list_of := (items...) => items
+                result := {}
+            ",
+                expr
+            );
+            eval(&source)
+        };
+        let grid = "transpose(list_of(list_of(1 2 3) list_of(4 5 6)))";
+        assert_eq!(
+            eval_with(grid).unwrap().to_string(),
+            "[[1, 4], [2, 5], [3, 6]]"
+        );
+        let twice = "transpose(transpose(list_of(list_of(1 2 3) list_of(4 5 6))))";
+        assert_eq!(
+            eval_with(twice).unwrap().to_string(),
+            "[[1, 2, 3], [4, 5, 6]]"
+        );
+        assert_eq!(eval_with("transpose(list())").unwrap().to_string(), "[]");
+        assert!(matches!(
+            eval_with("transpose(list_of(list_of(1 2) list_of(3)))"),
+            Err(ScriptError::ArgumentValue)
+        ));
+        assert!(matches!(
+            eval_with("transpose(list_of(list_of(1) 2))"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn with_cleanup_runs_after_success() {
+        let result = eval(
+            "
+            log := list()
+            value := with_cleanup(=> { log = push(log 1) 42 } => { log = push(log 2) })
+            result := push(log value)
+        ",
+        );
+        assert_eq!(result.unwrap().to_string(), "[1, 2, 42]");
+    }
+
+    #[test]
+    fn with_cleanup_runs_after_error() {
+        let mut exec = executor(
+            "
+            cleaned := 0
+            with_cleanup(=> { x := add(1 \"a\") } => { cleaned = 1 })
+            ",
+        );
+        assert!(matches!(
+            exec.run().unwrap(),
+            Err(ScriptError::ArgumentType)
+        ));
+        let cleaned = exec.globals.idents.get_index_of("cleaned").unwrap();
+        assert_eq!(exec.lookup_value(cleaned).unwrap().to_string(), "1");
+        // An error in the cleanup replaces the one from the body.
+        assert!(matches!(
+            eval("result := with_cleanup(=> { add(1 \"a\") } => { exit(2) })"),
+            Err(ScriptError::Exit(2))
+        ));
+        assert!(matches!(
+            eval("result := with_cleanup(1 => 2)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn to_number_reads_integers() {
+        let show = |source: &str| eval(source).map(|value| value.to_string());
+        assert_eq!(show("result := to_number(\"42\")").unwrap(), "42");
+        assert_eq!(show("result := to_number(\"-1_000\")").unwrap(), "-1000");
+        assert_eq!(
+            show("result := to_number(\"123456789012345678901234567890\")").unwrap(),
+            "123456789012345678901234567890"
+        );
+        for source in ["4.2", "1e3", "nope", "", "-", "_1", " 42"] {
+            let result = eval(&format!("result := to_number({:?})", source));
+            assert!(matches!(result, Ok(Value::None)), "{:?}", source);
+        }
+        assert!(matches!(
+            eval("result := to_number(42)"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let result = eval("result := to_bytes(\"aé€\")");
+        assert_eq!(result.unwrap().to_string(), "[97, 195, 169, 226, 130, 172]");
+        let result = eval("result := from_bytes(to_bytes(\"où est le café\"))");
+        assert_eq!(result.unwrap().to_string(), "où est le café");
+        assert_eq!(
+            eval("result := from_bytes(list())").unwrap().to_string(),
+            ""
+        );
+    }
+
+    #[test]
+    fn from_bytes_rejects_invalid_bytes() {
+        // A lone continuation byte, a truncated sequence, and numbers that aren't bytes.
+        for bytes in [
+            "push(list() 169)",
+            "push(list() 195)",
+            "push(list() 256)",
+            "push(list() -1)",
+        ] {
+            let result = eval(&format!("result := from_bytes({})", bytes));
+            assert!(
+                matches!(result, Err(ScriptError::ArgumentValue)),
+                "{}",
+                bytes
+            );
+        }
+        assert!(matches!(
+            eval("result := from_bytes(push(list() \"a\"))"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+
+    #[test]
+    fn sort_by_key_is_stable() {
+        let result = eval(
+            "
+            words := push(push(push(push(list() \"ccc\") \"a\") \"bb\") \"d\")
+            result := sort_by_key((word) => len(word) words)
+        ",
+        );
+        assert_eq!(result.unwrap().to_string(), r#"["a", "d", "bb", "ccc"]"#);
+        let result = eval(
+            "
+            calls := 0
+            key := (x) => { calls = add(calls 1) -x }
+            sorted := sort_by_key(key push(push(push(list() 2) 3) 1))
+            result := push(sorted calls)
+        ",
+        );
+        assert_eq!(result.unwrap().to_string(), "[3, 2, 1, 3]");
+        assert!(matches!(
+            eval("result := sort_by_key((x) => x push(list() \"a\"))"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+}
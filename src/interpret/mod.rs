@@ -3,22 +3,147 @@
 mod intrinsics;
 #[macro_use]
 mod macros;
+mod random;
 
-use crate::compile::{Code, Intrinsic, Op, Value, INTRINSIC_IDENTS};
+use crate::compile::{Code, Intrinsic, Lazy, Op, TinyInt, Value, INTRINSIC_IDENTS};
 use indexmap::IndexSet;
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::mem;
+use random::Rng;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::{fmt, mem};
+use std::time::Instant;
 
 #[derive(Debug, Default, Clone)]
 pub struct Executor {
-    code: Code,
-    idents: IndexSet<String>,
+    code: Rc<Code>,
+    globals: Globals,
     op_pointer: usize,
-    scope: HashMap<usize, Value>,
+    /// The variables declared so far, by their [local slot](Code::locals). Variables are kept behind shared pointers so that closures can capture them by reference.
+    scope: Vec<Option<Rc<RefCell<Value>>>>,
     stack: Vec<Value>,
     parent: Option<(Box<Self>, usize)>,
     depth: usize,
+    /// Where the arguments passed to this call frame begin on the stack, which is shared with the caller.
+    args_start: usize,
+    /// How many call frames are below this one.
+    call_depth: usize,
+    /// The identifier this frame's function was called through, if it was called directly by name.
+    callee: Option<usize>,
+}
+
+/// State shared by every call frame of an execution. It is handed down to a subroutine when it is entered and handed back to the caller when it exits.
+#[derive(Debug, Default, Clone)]
+struct Globals {
+    idents: IndexSet<String>,
+    /// The command line arguments passed to the script, not including the interpreter or source file.
+    args: Vec<String>,
+    /// Whether builtins that reach outside the interpreter into the host system (such as `env`) may run. Embedders can leave this disabled to sandbox untrusted scripts; such builtins then fail with [`ScriptError::AccessDenied`].
+    system_access: bool,
+    /// When the executor was created, which the `clock` builtin measures from.
+    start_time: Option<Instant>,
+    /// The generator behind the `random` builtin, which `seed` can reset.
+    rng: Rng,
+    /// Whether to print every operation to stderr as it runs.
+    trace: bool,
+    /// Whether the `breakpoint` builtin should pause to let variables be inspected from stdin.
+    interactive_breakpoints: bool,
+    /// The call frames that were active when the last uncaught error was raised, innermost first.
+    traceback: Option<Vec<Frame>>,
+    /// What arithmetic does when a result doesn't fit in an `isize`.
+    numeric_mode: NumericMode,
+    /// The most elements that the lists a script keeps alive may hold between them, if limited.
+    list_limit: Option<usize>,
+    /// Roughly how many elements live lists hold. It grows by the length of each list a builtin returns, and is only brought back down by counting the lists still reachable once it passes the limit.
+    list_elements: usize,
+    /// The most bits an arithmetic result may take up, if limited.
+    max_int_bits: Option<u64>,
+    /// Where `print` and `write` send their text.
+    output: Output,
+    /// How often each op and builtin has run, if profiling is enabled.
+    profile: Option<Profile>,
+}
+
+/// How many times each kind of op and each builtin ran, collected by an executor [with profiling](Executor::with_profile).
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    /// Counts by [op name](Op::name).
+    pub ops: HashMap<&'static str, u64>,
+    /// Counts by builtin name.
+    pub builtins: HashMap<&'static str, u64>,
+}
+
+/// Lists the counts from most to least frequent under a heading for ops and one for builtins.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (heading, counts) in [("ops", &self.ops), ("builtins", &self.builtins)] {
+            let mut counts: Vec<_> = counts.iter().collect();
+            counts.sort_by(|(name1, count1), (name2, count2)| {
+                count2.cmp(count1).then(name1.cmp(name2))
+            });
+            writeln!(f, "{}:", heading)?;
+            for (name, count) in counts {
+                writeln!(f, "    {:<16}{:>10}", name, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A shared writer for the output of a script, or stdout if there is none.
+#[derive(Default, Clone)]
+struct Output(Option<Rc<RefCell<dyn Write>>>);
+
+impl Output {
+    fn with_writer<T>(&self, f: impl FnOnce(&mut dyn Write) -> T) -> T {
+        match &self.0 {
+            Some(writer) => f(&mut *writer.borrow_mut()),
+            None => f(&mut io::stdout().lock()),
+        }
+    }
+}
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "Output(custom)"
+        } else {
+            "Output(stdout)"
+        })
+    }
+}
+
+/// How arithmetic handles results too large to be stored inline. There is no mode that falls back to floats yet, since there is no float type for it to produce.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NumericMode {
+    /// Numbers grow to arbitrary precision as needed.
+    #[default]
+    BigInt,
+    /// Any arithmetic result outside the range of an `isize` raises [`ScriptError::Overflow`].
+    Checked,
+}
+
+/// A call frame recorded in a traceback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// The name the function was called through, or `None` if it was anonymous or is the main program.
+    pub name: Option<String>,
+    /// The index of the operation that was running in this frame.
+    pub op: usize,
+    /// Whether this frame is the main program rather than a function call.
+    pub is_main: bool,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.name, self.is_main) {
+            (_, true) => f.write_str("in the main program")?,
+            (Some(name), false) => write!(f, "in `{}`", name)?,
+            (None, false) => f.write_str("in an anonymous function")?,
+        }
+        write!(f, " at op {}", self.op)
+    }
 }
 
 /// Errors within the interpreter. If this is ever publicly returned, that would constitute a serious bug.
@@ -30,6 +155,8 @@ pub enum InternalError {
     CallStackUnderflow,
     /// An operation requested a constant value that does not exist.
     ConstantNotFound,
+    /// An operation named a local slot that the current code doesn't have.
+    LocalNotFound,
     // /// Execution halted while values were still on the stack.
     // StackLeftovers,
 }
@@ -43,12 +170,79 @@ pub enum ScriptError {
     VariableRedeclared,
     /// The code attempted to call a non-code/non-builtin value.
     TypeNotCallable,
-    /// The code attempted to call a function with the wrong number of arguments.
-    ArgumentCount,
+    /// The code attempted to call a function with the wrong number of arguments. The name of the function is included if it's a builtin.
+    ArgumentCount {
+        expected: usize,
+        actual: usize,
+        name: Option<&'static str>,
+    },
     /// One or more arguments had an invalid type for the function called.
     ArgumentType,
     /// One or more arguments had the right type but an invalid value for the function called.
     ArgumentValue,
+    /// The code called a builtin that needs a capability this execution was not granted.
+    AccessDenied,
+    /// The lists the script keeps alive would hold more elements between them than the executor allows.
+    MemoryLimit,
+    /// An arithmetic result was bigger than the executor allows.
+    NumberTooLarge,
+    /// An arithmetic result was out of range in [`NumericMode::Checked`].
+    Overflow,
+    /// The `break` builtin was called with the given value. The innermost `loop` stops and returns the value, so this only reaches the embedder if `break` was called outside of a loop.
+    Break(Box<Value>),
+    /// The `assert_eq` builtin was called with two values that aren't equal.
+    AssertionFailed {
+        actual: Box<Value>,
+        expected: Box<Value>,
+    },
+    /// The code requested to halt with the given exit code, in the range `0..=255`. This isn't a failure, but it unwinds execution the same way so that the embedder decides what to do with it rather than the process being terminated out from under them.
+    Exit(i32),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VariableNotFound => f.write_str("variable not found"),
+            Self::VariableRedeclared => f.write_str("variable declared twice in the same scope"),
+            Self::TypeNotCallable => f.write_str("value is not callable"),
+            Self::ArgumentCount {
+                expected,
+                actual,
+                name,
+            } => {
+                if let Some(name) = name {
+                    write!(f, "`{}` ", name)?;
+                }
+                let plural = if *expected == 1 { "" } else { "s" };
+                write!(
+                    f,
+                    "expected {} argument{}, got {}",
+                    expected, plural, actual
+                )
+            }
+            Self::ArgumentType => f.write_str("argument has the wrong type"),
+            Self::ArgumentValue => f.write_str("argument has an invalid value"),
+            Self::AccessDenied => f.write_str("builtin requires system access"),
+            Self::MemoryLimit => f.write_str("lists hold more elements than the limit allows"),
+            Self::NumberTooLarge => f.write_str("number exceeds the maximum size"),
+            Self::Overflow => f.write_str("arithmetic overflow"),
+            Self::Break(_) => f.write_str("`break` called outside of a loop"),
+            Self::AssertionFailed { actual, expected } => {
+                // Quote strings, so that `"1"` can be told apart from `1`.
+                let repr = |value: &Value| match value {
+                    Value::Str(s) => format!("{:?}", s),
+                    value => value.to_string(),
+                };
+                write!(
+                    f,
+                    "assertion failed: expected {}, got {}",
+                    repr(expected),
+                    repr(actual)
+                )
+            }
+            Self::Exit(code) => write!(f, "exited with code {}", code),
+        }
+    }
 }
 
 pub type InternalResult<T> = Result<T, InternalError>;
@@ -58,28 +252,121 @@ pub type ExecResult<T> = InternalResult<ScriptResult<T>>;
 impl Executor {
     pub fn from_code(code: Code, idents: IndexSet<String>) -> Self {
         Self {
-            code,
-            idents,
+            code: Rc::new(code),
+            globals: Globals {
+                idents,
+                start_time: Some(Instant::now()),
+                rng: Rng::from_entropy(),
+                ..Globals::default()
+            },
             ..Self::default()
         }
     }
 
-    /// Adds every builtin function whose names appear anywhere in the current code to the current scope.
+    /// Sets the arguments returned by the `args` builtin.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.globals.args = args;
+        self
+    }
+
+    /// Grants or revokes access to builtins that interact with the host system. This is disabled by default.
+    pub fn with_system_access(mut self, allowed: bool) -> Self {
+        self.globals.system_access = allowed;
+        self
+    }
+
+    /// Enables or disables printing every operation to stderr along with the state of the stack as it runs.
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.globals.trace = trace;
+        self
+    }
+
+    /// Makes the `breakpoint` builtin pause for input on stdin after printing the state of the executor. This should only be enabled when stdin is a terminal, or the script may hang.
+    pub fn with_interactive_breakpoints(mut self, interactive: bool) -> Self {
+        self.globals.interactive_breakpoints = interactive;
+        self
+    }
+
+    /// Sends the output of `print` and `write` to the given writer instead of stdout. Text written with `write` may sit in the writer's buffer until the script calls `flush` or `print`.
+    #[cfg(test)]
+    pub fn with_output(mut self, writer: Rc<RefCell<dyn Write>>) -> Self {
+        self.globals.output = Output(Some(writer));
+        self
+    }
+
+    /// Chooses what happens when arithmetic overflows an `isize`. This is [`NumericMode::BigInt`] by default.
+    pub fn with_numeric_mode(mut self, mode: NumericMode) -> Self {
+        self.globals.numeric_mode = mode;
+        self
+    }
+
+    /// Limits how many elements all the lists a script keeps alive may hold between them, including the elements of nested lists, so that untrusted scripts can't exhaust memory. The length of each list a builtin returns is added to a running count. Once that count passes the limit, the lists still reachable from the script are counted in full, so lists that have since been dropped don't count against it; if they are still over, the builtin fails with [`ScriptError::MemoryLimit`]. Builtins also refuse to start building a list longer than the limit. There is no limit by default.
+    pub fn with_list_limit(mut self, limit: Option<usize>) -> Self {
+        self.globals.list_limit = limit;
+        self
+    }
+
+    /// Limits how many bits the result of an arithmetic operation may take up, not counting the sign, so that untrusted scripts can't hang the interpreter on enormous numbers. Results past the limit fail with [`ScriptError::NumberTooLarge`]. There is no limit by default.
+    pub fn with_max_int_bits(mut self, limit: Option<u64>) -> Self {
+        self.globals.max_int_bits = limit;
+        self
+    }
+
+    /// Enables or disables counting how many times each op and builtin runs, for [`take_profile`](Self::take_profile) to return. This is disabled by default.
+    pub fn with_profile(mut self, profile: bool) -> Self {
+        self.globals.profile = profile.then(Profile::default);
+        self
+    }
+
+    /// Adds every builtin function whose names appear anywhere in the current code to the current scope, each in a new local slot. Builtins whose names the code declares at the top level are left out, so that the program's own declaration takes their place instead of being a redeclaration.
     pub fn initialize_builtins(&mut self) {
         for (name, intrinsic) in INTRINSIC_IDENTS {
-            if let Some(name_index) = self.idents.get_index_of(name) {
-                self.scope.insert(name_index, Value::Builtin(intrinsic));
+            match self.globals.idents.get_index_of(name) {
+                Some(name_index) if !self.code.locals.contains(&name_index) => {
+                    let value = Rc::new(RefCell::new(Value::Builtin(intrinsic)));
+                    Rc::make_mut(&mut self.code).locals.push(name_index);
+                    let slot = self.code.locals.len() - 1;
+                    *self.local_mut(slot).expect("the slot was just added") = Some(value);
+                }
+                _ => {}
             }
         }
     }
 
+    /// Takes the value left behind by a finished program, which is only present if it was compiled with [`Return::Keep`](crate::compile::Return::Keep).
+    pub fn take_result(&mut self) -> Option<Value> {
+        self.stack.pop()
+    }
+
+    /// Takes the counts collected while running, if profiling was enabled.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.globals.profile.take()
+    }
+
+    /// Takes the call frames that were active when the error returned by [`run`](Self::run) was raised, innermost first.
+    pub fn take_traceback(&mut self) -> Option<Vec<Frame>> {
+        self.globals.traceback.take()
+    }
+
     pub fn run(&mut self) -> ExecResult<()> { // 58.15%
         loop {
             if let Some(&op) = self.code.ops.get(self.op_pointer) {
                 self.op_pointer += 1;
                 //println!("Current State:\n{:?}\n", self);
                 //println!("Running Op: {:?}", op);
-                double_try!(self.run_step(op)); // 55.02%
+                if self.globals.trace {
+                    self.trace_op(op);
+                }
+                if let Some(profile) = &mut self.globals.profile {
+                    *profile.ops.entry(op.name()).or_default() += 1;
+                }
+                if let Err(e) = self.run_step(op)? { // 55.02%
+                    // The error passes through every nested `run`, but only the innermost one sees all of its frames.
+                    if self.globals.traceback.is_none() {
+                        self.globals.traceback = Some(self.backtrace());
+                    }
+                    return Ok(Err(e));
+                }
             } else if self.depth > 0 {
                 self.exit_subroutine()?;
             } else {
@@ -108,18 +395,22 @@ impl Executor {
                 self.stack.push(val.clone());
             }
             Op::GetIdent(ident) => match self.lookup_value(ident) {
-                Ok(val) => {
-                    let val = val.clone();
-                    self.stack.push(val);
-                }
+                Ok(val) => self.stack.push(val),
                 Err(e) => {
                     eprintln!(
                         "{:?} accessed but not defined",
-                        self.idents.get_index(ident)
+                        self.globals.idents.get_index(ident)
                     );
                     return Ok(Err(e));
                 }
             },
+            Op::Negate => match self.pop_stack()? {
+                Value::Number(n) => {
+                    let negated = double_try!(self.check_overflow(Value::Number(-n)));
+                    self.stack.push(negated);
+                }
+                _ => return Ok(Err(ScriptError::ArgumentType)),
+            },
             Op::Drop => {
                 self.pop_stack()?;
             }
@@ -127,54 +418,281 @@ impl Executor {
                 let val = self.peek_stack()?.clone();
                 self.stack.push(val);
             }
-            Op::Declare(ident) => {
+            Op::Declare(slot) => {
                 let value = self.pop_stack()?;
-                match self.scope.entry(ident) {
+                match self.local_mut(slot)? {
                     // If the variable is already defined *in this scope*, it's a redeclaration.
-                    Entry::Occupied(_entry) => {
-                        return Ok(Err(ScriptError::VariableRedeclared));
-                    }
+                    Some(_) => return Ok(Err(ScriptError::VariableRedeclared)),
                     // Otherwise, initialize the variable with the given value.
-                    Entry::Vacant(space) => {
-                        space.insert(value);
-                    }
+                    space => *space = Some(Rc::new(RefCell::new(value))),
                 }
             }
+            Op::Shadow(slot) => {
+                let value = self.pop_stack()?;
+                *self.local_mut(slot)? = Some(Rc::new(RefCell::new(value)));
+            }
+            Op::GetLocal(slot) => match self.scope.get(slot) {
+                Some(Some(binding)) => {
+                    let value = binding.borrow().clone();
+                    self.stack.push(value);
+                }
+                _ => {
+                    let ident = self.local_ident(slot)?;
+                    return self.run_step(Op::GetIdent(ident));
+                }
+            },
+            Op::AssignLocal(slot) => match self.scope.get(slot) {
+                Some(Some(binding)) => {
+                    *binding.borrow_mut() =
+                        self.stack.pop().ok_or(InternalError::StackUnderflow)?;
+                }
+                _ => {
+                    let ident = self.local_ident(slot)?;
+                    return self.run_step(Op::Assign(ident));
+                }
+            },
             Op::Assign(ident) => {
                 let value = self.pop_stack()?;
-                match self.lookup_value_mut(ident) {
+                match self.lookup_binding(ident) {
                     // If the variable is already defined, then reassign it.
-                    Ok(entry_ref) => {
-                        *entry_ref = value;
+                    Ok(binding) => {
+                        *binding.borrow_mut() = value;
                     }
                     Err(e) => {
                         eprintln!(
                             "{:?} assigned to but not defined",
-                            self.idents.get_index(ident)
+                            self.globals.idents.get_index(ident)
                         );
                         return Ok(Err(e));
                     }
                 }
             }
+            Op::Closure(val_index) => {
+                let mut closure = self
+                    .code
+                    .constants
+                    .get(val_index)
+                    .cloned()
+                    .ok_or(InternalError::ConstantNotFound)?;
+                if let Value::Bytecode(code, _) = &mut closure {
+                    let captures = code
+                        .free_idents
+                        .iter()
+                        .filter_map(|&ident| {
+                            let binding = self.lookup_binding(ident).ok()?;
+                            Some((ident, Rc::clone(binding)))
+                        })
+                        .collect();
+                    // The constant is shared, so this makes the closure its own copy of the code.
+                    let code = Rc::make_mut(code);
+                    code.identity = Rc::new(());
+                    code.captures = captures;
+                }
+                self.stack.push(closure);
+            }
+            Op::Memoized(val_index) => {
+                let routine = match self.code.constants.get(val_index) {
+                    Some(Value::Bytecode(code, _)) => code.clone(),
+                    _ => return Err(InternalError::ConstantNotFound),
+                };
+                let args = &self.stack[self.args_start..];
+                // Arguments that can't be compared by value skip the cache entirely.
+                let key: Option<Vec<_>> = args.iter().map(Value::to_key).collect();
+                let cached = key
+                    .as_ref()
+                    .zip(self.code.memo.as_ref())
+                    .and_then(|(key, memo)| memo.borrow().get(key).cloned());
+                if let Some(result) = cached {
+                    self.stack.truncate(self.args_start);
+                    self.stack.push(result);
+                } else {
+                    let num_args = args.len();
+                    let result = double_try!(self.run_code_object(routine, num_args));
+                    if let (Some(key), Some(memo)) = (key, &self.code.memo) {
+                        memo.borrow_mut().insert(key, result.clone());
+                    }
+                    self.stack.push(result);
+                }
+            }
+            Op::DefaultArg(position, val_index) => {
+                if self.stack.len() - self.args_start <= position {
+                    match self.code.constants.get(val_index) {
+                        Some(Value::Bytecode(code, 0)) => {
+                            let code = code.clone();
+                            self.enter_subroutine(code, 0);
+                        }
+                        _ => return Err(InternalError::ConstantNotFound),
+                    }
+                }
+            }
+            Op::CollectRest(num_fixed) => {
+                let rest_start = self.args_start + num_fixed;
+                if self.stack.len() < rest_start {
+                    return Err(InternalError::StackUnderflow);
+                }
+                let rest = self.stack.split_off(rest_start);
+                self.stack.push(Value::List(rest));
+            }
             Op::Call(num_args) => match self.pop_stack()? {
                 Value::Bytecode(code, num_params) => {
-                    if num_params != num_args {
-                        return Ok(Err(ScriptError::ArgumentCount));
+                    if !code.accepts(num_params, num_args) {
+                        // Report whichever end of the accepted range was missed.
+                        let expected = if num_args < num_params {
+                            num_params
+                        } else {
+                            num_params + code.num_optional
+                        };
+                        return Ok(Err(ScriptError::ArgumentCount {
+                            expected,
+                            actual: num_args,
+                            name: None,
+                        }));
                     }
+                    // The callee is the last thing evaluated before the call, so it's only named if it was a plain variable.
+                    let callee = match self.code.ops.get(self.op_pointer.wrapping_sub(2)) {
+                        Some(&Op::GetIdent(ident)) => Some(ident),
+                        Some(&Op::GetLocal(slot)) => self.code.locals.get(slot).copied(),
+                        _ => None,
+                    };
                     self.enter_subroutine(code, num_args);
+                    self.callee = callee;
                 }
-                Value::Builtin(intrinsic) => {
-                    if intrinsic.num_params() != num_args {
-                        return Ok(Err(ScriptError::ArgumentCount));
-                    }
-                    double_try!(self.run_builtin(intrinsic));
-                }
+                Value::Builtin(intrinsic) => double_try!(self.call_builtin(intrinsic, num_args)),
                 _ => return Ok(Err(ScriptError::TypeNotCallable)),
             },
+            Op::CallBuiltin(intrinsic, num_args) => {
+                double_try!(self.call_builtin(intrinsic, num_args))
+            }
         }
         Ok(Ok(()))
     }
 
+    /// Runs a builtin on the arguments at the top of the stack, after checking that it was given the right number of them.
+    fn call_builtin(&mut self, intrinsic: Intrinsic, num_args: usize) -> ExecResult<()> {
+        if intrinsic.num_params() != num_args {
+            return Ok(Err(ScriptError::ArgumentCount {
+                expected: intrinsic.num_params(),
+                actual: num_args,
+                name: Some(intrinsic.name()),
+            }));
+        }
+        self.run_builtin(intrinsic)
+    }
+
+    /// Describes the variables in the current scope, the stack, and the position in the current code, for use by the `breakpoint` builtin. Builtin functions are left out of the variables to keep it readable.
+    fn debug_dump(&self) -> String {
+        let mut variables: Vec<(&str, String)> = self
+            .scope
+            .iter()
+            .zip(&self.code.locals)
+            .filter_map(|(binding, ident)| Some((*ident, binding.as_ref()?)))
+            .chain(
+                self.code
+                    .captures
+                    .iter()
+                    .map(|(ident, binding)| (*ident, binding)),
+            )
+            .filter(|(_, binding)| !matches!(*binding.borrow(), Value::Builtin(_)))
+            .map(|(ident, binding)| {
+                let name = self
+                    .globals
+                    .idents
+                    .get_index(ident)
+                    .map_or("?", String::as_str);
+                (name, binding.borrow().to_string())
+            })
+            .collect();
+        variables.sort();
+        let mut dump = format!("breakpoint at op {}\nvariables:\n", self.op_pointer);
+        for (name, value) in variables {
+            dump += &format!("    {} = {}\n", name, value);
+        }
+        dump += &format!("stack: {}", Value::List(self.stack.clone()));
+        dump
+    }
+
+    /// Lists this frame and every frame that called it, innermost first.
+    fn backtrace(&self) -> Vec<Frame> {
+        let mut frames = vec![];
+        let mut frame = Some(self);
+        while let Some(exec) = frame {
+            frames.push(Frame {
+                name: exec
+                    .callee
+                    .and_then(|ident| self.globals.idents.get_index(ident).cloned()),
+                op: exec.op_pointer.saturating_sub(1),
+                is_main: exec.call_depth == 0,
+            });
+            frame = exec.parent.as_ref().map(|(parent, _)| parent.as_ref());
+        }
+        frames
+    }
+
+    fn trace_op(&self, op: Op) {
+        let top = self
+            .stack
+            .last()
+            .map_or("<empty>".to_owned(), Value::to_string);
+        eprintln!(
+            "{:<20} stack size {:<4} top {}",
+            format!("{:?}", op),
+            self.stack.len(),
+            top
+        );
+    }
+
+    /// Passes the result of an arithmetic operation through, unless it overflowed in [`NumericMode::Checked`] or is bigger than the [size limit](Self::with_max_int_bits).
+    fn check_overflow(&self, result: Value) -> ExecResult<Value> {
+        if let Value::Number(TinyInt::Heap(h)) = &result {
+            if self.globals.numeric_mode == NumericMode::Checked {
+                return Ok(Err(ScriptError::Overflow));
+            }
+            if self
+                .globals
+                .max_int_bits
+                .is_some_and(|limit| h.bits() > limit)
+            {
+                return Ok(Err(ScriptError::NumberTooLarge));
+            }
+        }
+        Ok(Ok(result))
+    }
+
+    /// Counts a list a builtin returned towards the [list limit](Self::with_list_limit), failing if the lists that are alive would hold too many elements with it.
+    fn count_list(&mut self, value: &Value) -> ScriptResult<()> {
+        let (Some(limit), Value::List(list)) = (self.globals.list_limit, value) else {
+            return Ok(());
+        };
+        self.globals.list_elements = self.globals.list_elements.saturating_add(list.len());
+        if self.globals.list_elements > limit {
+            // Much of what was counted may have been dropped since, so count what's still reachable.
+            let mut seen = HashSet::new();
+            let size = count_elements(value, &mut seen);
+            self.globals.list_elements = self.live_elements(&mut seen).saturating_add(size);
+            if self.globals.list_elements > limit {
+                return Err(ScriptError::MemoryLimit);
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts the elements of every list reachable from this frame or the frames that called it. Lists being built by a builtin that hasn't returned yet aren't reachable, so they're left out.
+    fn live_elements(&self, seen: &mut HashSet<usize>) -> usize {
+        let mut total = 0;
+        let mut frame = Some(self);
+        while let Some(exec) = frame {
+            for value in &exec.stack {
+                total += count_elements(value, seen);
+            }
+            for binding in exec.scope.iter().flatten() {
+                total += count_binding(binding, seen);
+            }
+            total += count_code_elements(&exec.code, seen);
+            frame = exec.parent.as_ref().map(|(parent, _)| parent.as_ref());
+        }
+        total
+    }
+
     fn pop_stack(&mut self) -> InternalResult<Value> {
         self.stack.pop().ok_or(InternalError::StackUnderflow)
     }
@@ -183,45 +701,67 @@ impl Executor {
         self.stack.last().ok_or(InternalError::StackUnderflow)
     }
 
-    fn lookup_value(&self, name_index: usize) -> ScriptResult<&Value> {
-        self.scope
-            .get(&name_index)
+    /// Finds the variable with the given name, first in the current scope, then among the variables captured by the running closure, and finally in the caller.
+    fn lookup_binding(&self, name_index: usize) -> ScriptResult<&Rc<RefCell<Value>>> {
+        self.code
+            .locals
+            .iter()
+            .position(|&ident| ident == name_index)
+            .and_then(|slot| self.scope.get(slot)?.as_ref())
+            .or_else(|| {
+                self.code
+                    .captures
+                    .iter()
+                    .find(|(ident, _)| *ident == name_index)
+                    .map(|(_, binding)| binding)
+            })
             .or_else(|| {
                 self.parent
                     .as_ref()
-                    .and_then(|p| p.0.lookup_value(name_index).ok())
+                    .and_then(|p| p.0.lookup_binding(name_index).ok())
             })
             .ok_or(ScriptError::VariableNotFound)
     }
 
-    fn lookup_value_mut(&mut self, name_index: usize) -> ScriptResult<&mut Value> {
-        self.scope
-            .get_mut(&name_index)
-            .or_else(|| {
-                self.parent
-                    .as_mut()
-                    .and_then(|p| p.0.lookup_value_mut(name_index).ok())
-            })
-            .ok_or(ScriptError::VariableNotFound)
+    /// Returns the variable in the given local slot, making room for it first if need be. Only slots that the current code declares exist.
+    fn local_mut(&mut self, slot: usize) -> InternalResult<&mut Option<Rc<RefCell<Value>>>> {
+        if slot >= self.code.locals.len() {
+            return Err(InternalError::LocalNotFound);
+        }
+        if slot >= self.scope.len() {
+            self.scope.resize(slot + 1, None);
+        }
+        Ok(&mut self.scope[slot])
     }
 
-    // fn lookup_value_mut(&mut self, name_index: usize) -> ScriptResult<&mut Value> {
-    //     if let Some(val) = self.scope.get_mut(&name_index) {
-    //         Ok(val)
-    //     } else if let Some(parent) = &mut self.parent {
-    //         parent.0.lookup_value_mut(name_index)
-    //     } else {
-    //         Err(ScriptError::VariableNotFound)
-    //     }
-    // }
+    /// Returns the identifier of the variable in the given local slot.
+    fn local_ident(&self, slot: usize) -> InternalResult<usize> {
+        self.code
+            .locals
+            .get(slot)
+            .copied()
+            .ok_or(InternalError::LocalNotFound)
+    }
+
+    fn lookup_value(&self, name_index: usize) -> ScriptResult<Value> {
+        self.lookup_binding(name_index)
+            .map(|binding| binding.borrow().clone())
+    }
 
-    fn enter_subroutine(&mut self, routine: Code, _num_args: usize) { // 19.32%
+    fn enter_subroutine(&mut self, routine: Rc<Code>, num_args: usize) { // 19.32%
         let ptr = self.op_pointer;
-        let idents = mem::take(&mut self.idents); // mem::take 1.25%
-        let child = Self::from_code(routine, idents); // 8.11%
+        // Self::from_code 8.11%
+        let child = Self {
+            scope: Vec::with_capacity(routine.locals.len()),
+            code: routine,
+            globals: mem::take(&mut self.globals), // mem::take 1.25%
+            ..Self::default()
+        };
         // `self` becomes `parent`, and `child` becomes `self`
         let mut parent = mem::replace(self, child); // 1.60%
         self.stack = mem::take(&mut parent.stack);
+        self.args_start = self.stack.len() - num_args;
+        self.call_depth = parent.call_depth + 1;
         self.parent = Some((Box::new(parent), ptr)); // 2.19%
         self.op_pointer = 0;
         self.depth += 1;
@@ -231,24 +771,39 @@ impl Executor {
         let (parent, ptr) = mem::take(&mut self.parent).ok_or(InternalError::CallStackUnderflow)?; // mem::take 0.08%, ok_or 0.08%
         let child = mem::replace(self, *parent); // 2.61%
         self.stack = child.stack;
+        self.globals = child.globals;
         self.op_pointer = ptr;
         // self.depth -= 1;
         Ok(())
         // freeing and dropping 8.99%
     }
 
-    fn run_code_object(&mut self, code: Code) -> ExecResult<Value> { // 91.65%
+    /// Runs the code with the given number of arguments already pushed to the stack, and returns its result.
+    fn run_code_object(&mut self, code: Rc<Code>, num_args: usize) -> ExecResult<Value> { // 91.65%
         // Run as if we are the main execution.
         let depth = self.depth;
-        self.enter_subroutine(code, 0); // 19.32%
+        let call_depth = self.call_depth;
+        let stack_len = self.stack.len() - num_args;
+        self.enter_subroutine(code, num_args); // 19.32%
         self.depth = 0;
-        double_try!(self.run()); // 58.15%
+        if let Err(e) = self.run()? { // 58.15%
+            // Unwind every frame the error passed through, so that the caller can carry on if it handles the error.
+            while self.call_depth > call_depth {
+                self.exit_subroutine()?;
+            }
+            self.depth = depth;
+            self.stack.truncate(stack_len);
+            return Ok(Err(e));
+        }
         self.exit_subroutine()?; // 13.24%
         self.depth = depth;
         self.pop_stack().map(Ok) // 0.65%
     }
 
     fn run_builtin(&mut self, intrinsic: Intrinsic) -> ExecResult<()> { // 19.05%
+        if let Some(profile) = &mut self.globals.profile {
+            *profile.builtins.entry(intrinsic.name()).or_default() += 1;
+        }
         let return_value = double_try!(match intrinsic {
             Intrinsic::Print => intrinsics::print(self),
             Intrinsic::While => intrinsics::while_loop(self),
@@ -266,8 +821,604 @@ impl Executor {
             Intrinsic::Filter => intrinsics::filter(self),
             Intrinsic::Zip => intrinsics::zip(self),
             Intrinsic::At => intrinsics::at(self),
+            Intrinsic::Args => intrinsics::args(self),
+            Intrinsic::Env => intrinsics::env(self),
+            Intrinsic::Exit => intrinsics::exit(self),
+            Intrinsic::Clock => intrinsics::clock(self),
+            Intrinsic::Random => intrinsics::random(self),
+            Intrinsic::Seed => intrinsics::seed(self),
+            Intrinsic::Shuffle => intrinsics::shuffle(self),
+            Intrinsic::Sample => intrinsics::sample(self),
+            Intrinsic::Unique => intrinsics::unique(self),
+            Intrinsic::GroupBy => intrinsics::group_by(self),
+            Intrinsic::Partition => intrinsics::partition(self),
+            Intrinsic::ZipWith => intrinsics::zip_with(self),
+            Intrinsic::ZipN => intrinsics::zip_n(self),
+            Intrinsic::Insert => intrinsics::insert(self),
+            Intrinsic::Remove => intrinsics::remove(self),
+            Intrinsic::SetAt => intrinsics::set_at(self),
+            Intrinsic::UpdateAt => intrinsics::update_at(self),
+            Intrinsic::Replicate => intrinsics::replicate(self),
+            Intrinsic::Seq => intrinsics::seq(self),
+            Intrinsic::Memoize => intrinsics::memoize(self),
+            Intrinsic::Breakpoint => intrinsics::breakpoint(self),
+            Intrinsic::IsNone => intrinsics::is_none(self),
+            Intrinsic::IsNumber => intrinsics::is_number(self),
+            Intrinsic::IsString => intrinsics::is_string(self),
+            Intrinsic::IsList => intrinsics::is_list(self),
+            Intrinsic::IsCallable => intrinsics::is_callable(self),
+            Intrinsic::Eq => intrinsics::eq(self),
+            Intrinsic::Contains => intrinsics::contains(self),
+            Intrinsic::DoWhile => intrinsics::do_while(self),
+            Intrinsic::Loop => intrinsics::infinite_loop(self),
+            Intrinsic::Break => intrinsics::break_loop(self),
+            Intrinsic::ToRadix => intrinsics::to_radix(self),
+            Intrinsic::MinBy => intrinsics::min_by(self),
+            Intrinsic::MaxBy => intrinsics::max_by(self),
+            Intrinsic::Chunks => intrinsics::chunks(self),
+            Intrinsic::Windows => intrinsics::windows(self),
+            Intrinsic::Builtins => intrinsics::builtins(self),
+            Intrinsic::Help => intrinsics::help(self),
+            Intrinsic::Cons => intrinsics::cons(self),
+            Intrinsic::FoldLeft => intrinsics::fold_left(self),
+            Intrinsic::FoldRight => intrinsics::fold_right(self),
+            Intrinsic::Iterate => intrinsics::iterate(self),
+            Intrinsic::Sum => intrinsics::sum(self),
+            Intrinsic::Product => intrinsics::product(self),
+            Intrinsic::Clamp => intrinsics::clamp(self),
+            Intrinsic::Sign => intrinsics::sign(self),
+            Intrinsic::TakeWhile => intrinsics::take_while(self),
+            Intrinsic::DropWhile => intrinsics::drop_while(self),
+            Intrinsic::Default => intrinsics::default_value(self),
+            Intrinsic::DefaultWith => intrinsics::default_with(self),
+            Intrinsic::Match => intrinsics::match_value(self),
+            Intrinsic::Write => intrinsics::write(self),
+            Intrinsic::Flush => intrinsics::flush(self),
+            Intrinsic::Upper => intrinsics::upper(self),
+            Intrinsic::Lower => intrinsics::lower(self),
+            Intrinsic::Trim => intrinsics::trim(self),
+            Intrinsic::StartsWith => intrinsics::starts_with(self),
+            Intrinsic::EndsWith => intrinsics::ends_with(self),
+            Intrinsic::Replace => intrinsics::replace(self),
+            Intrinsic::Substring => intrinsics::substring(self),
+            Intrinsic::Find => intrinsics::find(self),
+            Intrinsic::Range => intrinsics::range(self),
+            Intrinsic::Collect => intrinsics::collect(self),
+            Intrinsic::Each => intrinsics::each(self),
+            Intrinsic::Not => intrinsics::not(self),
+            Intrinsic::Chars => intrinsics::chars(self),
+            Intrinsic::Ord => intrinsics::ord(self),
+            Intrinsic::Chr => intrinsics::chr(self),
+            Intrinsic::DeepMap => intrinsics::deep_map(self),
+            Intrinsic::AssertEq => intrinsics::assert_eq(self),
+            Intrinsic::Once => intrinsics::once(self),
+            Intrinsic::Union => intrinsics::union(self),
+            Intrinsic::Intersection => intrinsics::intersection(self),
+            Intrinsic::Difference => intrinsics::difference(self),
+            Intrinsic::Transpose => intrinsics::transpose(self),
+            Intrinsic::WithCleanup => intrinsics::with_cleanup(self),
+            Intrinsic::ToNumber => intrinsics::to_number(self),
+            Intrinsic::ToBytes => intrinsics::to_bytes(self),
+            Intrinsic::FromBytes => intrinsics::from_bytes(self),
+            Intrinsic::SortByKey => intrinsics::sort_by_key(self),
         });
+        if let Err(e) = self.count_list(&return_value) {
+            return Ok(Err(e));
+        }
         self.stack.push(return_value);
         Ok(Ok(()))
     }
 }
+
+/// Counts the elements of a value's lists, including nested ones and those held by closures. Variables and caches shared between closures are only counted the first time they're seen, by their address.
+fn count_elements(value: &Value, seen: &mut HashSet<usize>) -> usize {
+    let mut total = 0;
+    // Lists are walked with a stack rather than recursion, since they may be nested arbitrarily deep.
+    let mut work = vec![value];
+    while let Some(value) = work.pop() {
+        match value {
+            Value::List(list) => {
+                total += list.len();
+                work.extend(list);
+            }
+            Value::Bytecode(code, _) => total += count_code_elements(code, seen),
+            Value::Lazy(lazy) => {
+                let mut lazy = lazy.as_ref();
+                while let Lazy::Map(code, inner) | Lazy::Filter(code, inner) = lazy {
+                    total += count_code_elements(code, seen);
+                    lazy = inner;
+                }
+            }
+            Value::None | Value::Number(_) | Value::Str(_) | Value::Builtin(_) => {}
+        }
+    }
+    total
+}
+
+fn count_binding(binding: &Rc<RefCell<Value>>, seen: &mut HashSet<usize>) -> usize {
+    if seen.insert(Rc::as_ptr(binding) as usize) {
+        count_elements(&binding.borrow(), seen)
+    } else {
+        0
+    }
+}
+
+fn count_code_elements(code: &Code, seen: &mut HashSet<usize>) -> usize {
+    let mut total = 0;
+    for constant in &code.constants {
+        total += count_elements(constant, seen);
+    }
+    for (_, binding) in &code.captures {
+        total += count_binding(binding, seen);
+    }
+    if let Some(memo) = &code.memo {
+        if seen.insert(Rc::as_ptr(memo) as usize) {
+            for (_, value) in memo.borrow().iter() {
+                total += count_elements(value, seen);
+            }
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::compile::compile;
+    use crate::parse::parse;
+
+    /// Prepares an executor for the given source code, panicking if it fails to parse.
+    pub fn executor(source: &str) -> Executor {
+        let (code, idents) = compile(parse(source).expect("test source should parse"));
+        let mut exec = Executor::from_code(code, idents);
+        exec.initialize_builtins();
+        exec
+    }
+
+    /// Runs an executor to completion and returns the value of its `result` variable.
+    pub fn result_of(mut exec: Executor) -> ScriptResult<Value> {
+        exec.run().expect("internal interpreter error")?;
+        let name_index = exec
+            .globals
+            .idents
+            .get_index_of("result")
+            .expect("test source should declare `result`");
+        exec.lookup_value(name_index)
+    }
+
+    /// Runs the given source code and returns the value of its `result` variable.
+    pub fn eval(source: &str) -> ScriptResult<Value> {
+        result_of(executor(source))
+    }
+
+    #[test]
+    fn kept_result_is_taken() {
+        use crate::compile::{compile_with, Return};
+        let (code, idents) = compile_with(parse("x := 4 push(list() x)").unwrap(), Return::Keep);
+        let mut exec = Executor::from_code(code, idents);
+        exec.initialize_builtins();
+        exec.run().unwrap().unwrap();
+        assert_eq!(exec.take_result().unwrap().to_string(), "[4]");
+        assert!(exec.take_result().is_none());
+    }
+
+    #[test]
+    fn too_few_arguments() {
+        let err = eval("f := (a b) => a result := f(1)").unwrap_err();
+        assert!(matches!(
+            err,
+            ScriptError::ArgumentCount {
+                expected: 2,
+                actual: 1,
+                name: None
+            }
+        ));
+        assert_eq!(err.to_string(), "expected 2 arguments, got 1");
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let err = eval("result := len(list() 1 2)").unwrap_err();
+        assert!(matches!(
+            err,
+            ScriptError::ArgumentCount {
+                expected: 1,
+                actual: 3,
+                name: Some("len")
+            }
+        ));
+        assert_eq!(err.to_string(), "`len` expected 1 argument, got 3");
+    }
+
+    #[test]
+    fn block_runs_immediately_but_lambda_defers() {
+        let value = eval("result := {5}").unwrap();
+        assert!(matches!(value, Value::Number(_)));
+        let value = eval("result := => 5").unwrap();
+        assert!(matches!(value, Value::Bytecode(_, 0)));
+        let value = eval("f := => 5 result := f()").unwrap();
+        assert_eq!(value.to_string(), "5");
+        let value =
+            eval("x := 3 n := 0 while(=> x => { x = sub(x 1) n = add(n 1) }) result := n").unwrap();
+        assert_eq!(value.to_string(), "3");
+    }
+
+    #[test]
+    fn rest_parameter() {
+        let source = "f := (a rest...) => push(push(list() a) rest)";
+        let value = eval(&format!("{} result := f(1)", source)).unwrap();
+        assert_eq!(value.to_string(), "[1, []]");
+        let value = eval(&format!("{} result := f(1 2 3 4)", source)).unwrap();
+        assert_eq!(value.to_string(), "[1, [2, 3, 4]]");
+        let value = eval(&format!("{} result := push(list() f(1 2))", source)).unwrap();
+        assert_eq!(value.to_string(), "[[1, [2]]]");
+        let value = eval("result := map((x rest...) => push(rest x) push(list() 1))").unwrap();
+        assert_eq!(value.to_string(), "[[1]]");
+        let value = eval("f := (rest...) => len(rest) result := f()").unwrap();
+        assert_eq!(value.to_string(), "0");
+        let value = eval(&format!("{} result := f", source)).unwrap();
+        assert_eq!(value.to_string(), "<1+ argument function>");
+        let err = eval(&format!("{} result := f()", source)).unwrap_err();
+        assert!(matches!(
+            err,
+            ScriptError::ArgumentCount {
+                expected: 1,
+                actual: 0,
+                name: None
+            }
+        ));
+    }
+
+    #[test]
+    fn default_parameter() {
+        let source = "f := (a = 1 b = 10) => push(push(list() a) b)";
+        let value = eval(&format!("{} result := f()", source)).unwrap();
+        assert_eq!(value.to_string(), "[1, 10]");
+        let value = eval(&format!("{} result := f(2)", source)).unwrap();
+        assert_eq!(value.to_string(), "[2, 10]");
+        let value = eval(&format!("{} result := push(list() f(2 3))", source)).unwrap();
+        assert_eq!(value.to_string(), "[[2, 3]]");
+        let value = eval(&format!("{} result := f", source)).unwrap();
+        assert_eq!(value.to_string(), "<0-2 argument function>");
+        let err = eval(&format!("{} result := f(1 2 3)", source)).unwrap_err();
+        assert!(matches!(
+            err,
+            ScriptError::ArgumentCount {
+                expected: 2,
+                actual: 3,
+                name: None
+            }
+        ));
+        let value = eval("f := (a b = add(a 1)) => b result := f(1)");
+        assert!(matches!(value, Err(ScriptError::VariableNotFound)));
+        let value =
+            eval("x := 5 f := (a b = add(x 1) rest...) => push(push(rest a) b) result := f(1)");
+        assert_eq!(value.unwrap().to_string(), "[1, 6]");
+    }
+
+    #[test]
+    fn let_shadows_current_scope() {
+        let value = eval("x := 1 let x = add(x 1) let x = mul(x 10) result := x").unwrap();
+        assert_eq!(value.to_string(), "20");
+        assert!(matches!(
+            eval("x := 1 x := 2 result := x"),
+            Err(ScriptError::VariableRedeclared)
+        ));
+        // Shadowing inside a block declares a new variable rather than reassigning the outer one.
+        let value =
+            eval("x := 1 inner := { let x = 2 x } result := push(push(list() x) inner)").unwrap();
+        assert_eq!(value.to_string(), "[1, 2]");
+        let value = eval("x := 1 { x = 2 } result := x").unwrap();
+        assert_eq!(value.to_string(), "2");
+    }
+
+    #[test]
+    fn block_scope() {
+        assert!(matches!(
+            eval("{ x := 5 } result := x"),
+            Err(ScriptError::VariableNotFound)
+        ));
+        assert!(matches!(
+            eval("{ x = 5 } result := 0"),
+            Err(ScriptError::VariableNotFound)
+        ));
+        let value = eval("x := 1 { x = 5 } result := x").unwrap();
+        assert_eq!(value.to_string(), "5");
+        let value = eval("x := 1 { { x = add(x 1) } } result := x").unwrap();
+        assert_eq!(value.to_string(), "2");
+        // A declaration inside a block shadows the outer variable instead of redeclaring it.
+        let value = eval("x := 1 inner := { x := 5 x = 6 x } result := push(push(list() x) inner)")
+            .unwrap();
+        assert_eq!(value.to_string(), "[1, 6]");
+        let value = eval("{ x := 1 } { x := 2 } result := 0").unwrap();
+        assert_eq!(value.to_string(), "0");
+    }
+
+    #[test]
+    fn closures_capture_by_reference() {
+        let counter = "make_counter := () => {
+            count := 0
+            () => {
+                count = add(count 1)
+                count
+            }
+        }";
+        let value = eval(&format!(
+            "{} c := make_counter() d := make_counter() c() c() d() result := push(push(list() c()) d())",
+            counter
+        ))
+        .unwrap();
+        assert_eq!(value.to_string(), "[3, 2]");
+        let value =
+            eval("make_adder := (x) => (y) => add(x y) add5 := make_adder(5) result := add5(2)")
+                .unwrap();
+        assert_eq!(value.to_string(), "7");
+        // The variable seen is the one in scope where the closure was created, not where it was called.
+        let value = eval("a := 1 f := => a result := { a := 2 f() }").unwrap();
+        assert_eq!(value.to_string(), "1");
+        let value = eval("a := 1 f := => a a = 2 result := f()").unwrap();
+        assert_eq!(value.to_string(), "2");
+        // Variables that didn't exist yet when the closure was created are still found through the caller.
+        let value = eval("f := => later later := 3 result := f()").unwrap();
+        assert_eq!(value.to_string(), "3");
+        let value = eval("n := 2 result := map((x) => add(x n) push(push(list() 1) 2))").unwrap();
+        assert_eq!(value.to_string(), "[3, 4]");
+    }
+
+    #[test]
+    fn closures_that_capture_themselves_leak() {
+        let leaked_after = |source: &str, name: &str| {
+            let mut exec = executor(source);
+            exec.run().unwrap().unwrap();
+            let name_index = exec.globals.idents.get_index_of(name).unwrap();
+            let binding = Rc::downgrade(exec.lookup_binding(name_index).unwrap());
+            drop(exec);
+            binding.upgrade().is_some()
+        };
+        assert!(!leaked_after("x := 1 f := () => x", "x"));
+        assert!(!leaked_after("x := 1 f := () => x", "f"));
+        // A recursive function declared in one go finds itself through the caller instead of capturing itself.
+        assert!(!leaked_after("f := () => f()", "f"));
+        // `f` captures its own variable, so the closure and the variable keep each other alive.
+        assert!(leaked_after("f := 0 f = () => f", "f"));
+    }
+
+    #[test]
+    fn breakpoint_dump() {
+        let mut exec = executor("x := 5 items := push(list() \"hi\") breakpoint()");
+        exec.run().unwrap().unwrap();
+        assert_eq!(
+            exec.debug_dump(),
+            format!(
+                "breakpoint at op {}\nvariables:\n    items = [\"hi\"]\n    x = 5\nstack: []",
+                exec.code.ops.len()
+            )
+        );
+        assert_eq!(
+            eval("f := (a) => breakpoint() result := f(1)")
+                .unwrap()
+                .to_string(),
+            "None"
+        );
+    }
+
+    #[test]
+    fn traceback_three_calls_deep() {
+        let mut exec =
+            executor("c := () => add(1 list()) b := () => c() a := () => { b() } result := a()");
+        assert!(matches!(
+            exec.run().unwrap(),
+            Err(ScriptError::ArgumentType)
+        ));
+        let frames = exec.take_traceback().unwrap();
+        let names: Vec<_> = frames.iter().map(|frame| frame.name.as_deref()).collect();
+        // The block inside `a` is an anonymous function of its own.
+        assert_eq!(names, [Some("c"), Some("b"), None, Some("a"), None]);
+        assert!(frames[4].is_main && !frames[3].is_main);
+        assert!(exec.take_traceback().is_none());
+    }
+
+    #[test]
+    fn list_limit() {
+        let source = "l := list() while(() => 1 () => { l = push(l 0) }) result := l";
+        let exec = executor(source).with_list_limit(Some(100));
+        assert!(matches!(result_of(exec), Err(ScriptError::MemoryLimit)));
+        let exec = executor("result := replicate(5 0)").with_list_limit(Some(5));
+        assert_eq!(result_of(exec).unwrap().to_string(), "[0, 0, 0, 0, 0]");
+        let exec = executor("result := replicate(6 0)").with_list_limit(Some(5));
+        assert!(matches!(result_of(exec), Err(ScriptError::MemoryLimit)));
+    }
+
+    #[test]
+    fn list_limit_counts_every_live_list() {
+        let run = |source| result_of(executor(source).with_list_limit(Some(100)));
+        // Each list is under the limit, but not both together.
+        let result = run("a := replicate(60 0) b := replicate(60 0) result := 1");
+        assert!(matches!(result, Err(ScriptError::MemoryLimit)));
+        // Once the recount happens, it includes the elements of nested lists.
+        let result = run("a := replicate(10 replicate(9 0)) b := replicate(90 0) result := 1");
+        assert!(matches!(result, Err(ScriptError::MemoryLimit)));
+        assert!(run("result := replicate(10 replicate(9 0))").is_ok());
+        // Lists that have been dropped don't count.
+        let source =
+            "i := 0 while(=> sub(i 50) () => { x := replicate(60 0) i = add(i 1) }) result := i";
+        assert_eq!(run(source).unwrap().to_string(), "50");
+        let result = run("a := replicate(60 0) a = 0 b := replicate(60 0) result := 1");
+        assert_eq!(result.unwrap().to_string(), "1");
+    }
+
+    #[test]
+    fn int_size_limit() {
+        // Squaring 2 twenty times gives 2^(2^20), which has over a million bits.
+        let source = "result := last(iterate((x) => mul(x x) 2 21))";
+        let exec = executor(source).with_max_int_bits(Some(4096));
+        assert!(matches!(result_of(exec), Err(ScriptError::NumberTooLarge)));
+        let exec =
+            executor("result := last(iterate((x) => mul(x x) 2 12))").with_max_int_bits(Some(4096));
+        assert_eq!(result_of(exec).unwrap().to_string().len(), 617);
+        // Numbers that fit in an `isize` are always allowed.
+        let exec = executor("result := add(1 1)").with_max_int_bits(Some(0));
+        assert_eq!(result_of(exec).unwrap().to_string(), "2");
+    }
+
+    /// Programs whose last expression is an assignment, possibly nested in another one or a block, lambda, or call.
+    const ASSIGNMENTS: &[&str] = &[
+        "x := y := 5",
+        "x := 0 y := 0 x = y = 5",
+        "let x = y := 5",
+        "x := let y = z := 5",
+        "x := { y := 5 }",
+        "x := { }",
+        "x := -{ y := 5 }",
+        "f := (a) => b := a f(5)",
+        "f := (a) => b := a x := f(y := 5)",
+        "x := (a) => b := a",
+    ];
+
+    #[test]
+    fn kept_assignments_leave_one_value() {
+        use crate::compile::{compile_with, Return};
+        for source in ASSIGNMENTS {
+            let (code, idents) = compile_with(parse(source).unwrap(), Return::Keep);
+            let mut exec = Executor::from_code(code, idents);
+            exec.initialize_builtins();
+            exec.run().unwrap().unwrap();
+            assert_eq!(exec.stack.len(), 1, "in `{}`", source);
+        }
+        let exec = executor("x := y := 5 result := push(push(list() x) y)");
+        assert_eq!(result_of(exec).unwrap().to_string(), "[5, 5]");
+    }
+
+    #[test]
+    fn discarded_assignments_leave_nothing() {
+        for source in ASSIGNMENTS {
+            let mut exec = executor(source);
+            exec.run().unwrap().unwrap();
+            assert!(exec.stack.is_empty(), "in `{}`", source);
+        }
+    }
+
+    #[test]
+    fn chained_assignment() {
+        let values = "result := push(push(push(list() a) b) c)";
+        let result = eval(&format!("a := b := 5 c := 0 {}", values));
+        assert_eq!(result.unwrap().to_string(), "[5, 5, 0]");
+        let result = eval(&format!("a := 1 b := 2 c := a = b = 5 {}", values));
+        assert_eq!(result.unwrap().to_string(), "[5, 5, 5]");
+    }
+
+    #[test]
+    fn profile_counts() {
+        let mut exec = executor("f := (a) => add(a 1) x := f(f(1))").with_profile(true);
+        exec.run().unwrap().unwrap();
+        let profile = exec.take_profile().unwrap();
+        let mut ops: Vec<_> = profile.ops.into_iter().collect();
+        ops.sort();
+        assert_eq!(
+            ops,
+            [
+                ("Call", 2),
+                ("CallBuiltin", 2),
+                ("Declare", 4),
+                ("GetConstant", 4),
+                ("GetLocal", 4)
+            ]
+        );
+        assert_eq!(
+            profile.builtins.into_iter().collect::<Vec<_>>(),
+            [("add", 2)]
+        );
+        assert!(executor("x := 1").take_profile().is_none());
+    }
+
+    #[test]
+    fn empty_program() {
+        use crate::compile::{compile_with, Return};
+        for source in ["", "  \n\t\n"] {
+            let mut exec = executor(source);
+            exec.run().unwrap().unwrap();
+            assert!(exec.take_result().is_none());
+            let (code, idents) = compile_with(parse(source).unwrap(), Return::Keep);
+            let mut exec = Executor::from_code(code, idents);
+            exec.run().unwrap().unwrap();
+            assert_eq!(exec.take_result(), Some(Value::None));
+        }
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let result = eval("x := 10 x += 5 x -= 3 x *= 4 { x /= 6 } x %= 5 result := x");
+        assert_eq!(result.unwrap().to_string(), "3");
+        let result = eval("x := 1 result := x += 1");
+        assert_eq!(result.unwrap().to_string(), "2");
+        assert!(matches!(
+            eval("y += 1 result := y"),
+            Err(ScriptError::VariableNotFound)
+        ));
+    }
+
+    #[test]
+    fn shadowed_builtin() {
+        let result = eval(
+            "before := add(1 2) add = (a b) => sub(a b) result := push(push(push(list() before) add(1 2)) add)",
+        );
+        assert_eq!(
+            result.unwrap().to_string(),
+            "[3, -1, <2 argument function>]"
+        );
+        let result = eval("f := (add) => add(1 2) result := f(mul)");
+        assert_eq!(result.unwrap().to_string(), "2");
+    }
+
+    #[test]
+    fn local_read_before_declaration() {
+        let result = eval("x := 1 result := { before := x x := 2 push(push(list() before) x) }");
+        assert_eq!(result.unwrap().to_string(), "[1, 2]");
+        let result = eval("x := 1 { x = 3 x := 2 } result := x");
+        assert_eq!(result.unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn top_level_declaration_of_builtin_name() {
+        let result = eval("sum := fold((a b) => add(a b) push(push(list() 1) 2)) result := sum");
+        assert_eq!(result.unwrap().to_string(), "3");
+        // As in examples/maths.bli, which predates the `range` builtin.
+        let result =
+            eval("range := (start end) => push(push(list() start) end) result := range(1 4)");
+        assert_eq!(result.unwrap().to_string(), "[1, 4]");
+        let result = eval("not := (x) => sub(1 x) result := push(push(list() not(0)) not(1))");
+        assert_eq!(result.unwrap().to_string(), "[1, 0]");
+    }
+
+    #[test]
+    fn undeclared_slots_are_refused() {
+        let mut exec = Executor {
+            stack: vec![Value::None, Value::None],
+            ..Executor::default()
+        };
+        assert!(matches!(
+            exec.run_step(Op::Declare(1 << 40)),
+            Err(InternalError::LocalNotFound)
+        ));
+        assert!(matches!(
+            exec.run_step(Op::Shadow(0)),
+            Err(InternalError::LocalNotFound)
+        ));
+        assert!(exec.scope.is_empty());
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(eval("x := 5 result := -x").unwrap().to_string(), "-5");
+        assert_eq!(
+            eval("f := (a) => mul(a 2) result := -f(-3)")
+                .unwrap()
+                .to_string(),
+            "6"
+        );
+        assert!(matches!(
+            eval("x := list() result := -x"),
+            Err(ScriptError::ArgumentType)
+        ));
+    }
+}
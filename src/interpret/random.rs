@@ -0,0 +1,77 @@
+//! A small, dependency-free pseudo-random number generator for the `random` family of builtins.
+
+use crate::compile::TinyInt;
+use num_bigint::{BigInt, Sign};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// A SplitMix64 generator. It is fast and statistically decent, but not suitable for anything security related.
+#[derive(Debug, Default, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a generator whose output will be the same every time for a given seed.
+    pub const fn from_seed(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Creates a generator seeded from the randomness the standard library uses for `HashMap`.
+    pub fn from_entropy() -> Self {
+        Self::from_seed(RandomState::new().build_hasher().finish())
+    }
+
+    /// Creates a generator from a Bling number, so that every number is a distinct seed.
+    pub fn from_number(seed: &TinyInt) -> Self {
+        let seed = match seed {
+            TinyInt::Inline(x) => *x as u64,
+            TinyInt::Heap(h) => h
+                .iter_u64_digits()
+                .fold(h.sign() as u64, |acc, digit| acc.rotate_left(7) ^ digit),
+        };
+        Self::from_seed(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a uniformly distributed index in `[0, bound)`. `bound` must be nonzero.
+    pub fn index_below(&mut self, bound: usize) -> usize {
+        self.below_u64(bound as u64) as usize
+    }
+
+    fn below_u64(&mut self, bound: u64) -> u64 {
+        // Reject the top sliver of outputs that would bias the result toward small values.
+        let zone = u64::MAX - (u64::MAX - bound + 1) % bound;
+        loop {
+            let x = self.next_u64();
+            if x <= zone {
+                return x % bound;
+            }
+        }
+    }
+
+    /// Returns a uniformly distributed integer in `[0, bound)`, or `None` if `bound` isn't positive.
+    pub fn below(&mut self, bound: &TinyInt) -> Option<TinyInt> {
+        if bound.is_negative() || bound.is_zero() {
+            return None;
+        }
+        Some(match bound {
+            &TinyInt::Inline(bound) => TinyInt::Inline(self.below_u64(bound as u64) as isize),
+            TinyInt::Heap(bound) => {
+                // 64 extra bits make the modulo bias vanishingly small.
+                let num_words = bound.bits() / 64 + 2;
+                let bytes: Vec<u8> = (0..num_words)
+                    .flat_map(|_| self.next_u64().to_le_bytes())
+                    .collect();
+                (BigInt::from_bytes_le(Sign::Plus, &bytes) % bound).into()
+            }
+        })
+    }
+}
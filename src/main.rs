@@ -7,44 +7,290 @@ mod interpret;
 mod parse;
 mod sysexits;
 
-use compile::compile;
-use interpret::Executor;
-use parse::parse;
+use compile::Code;
+use compile::{
+    compile, compile_with, discards_result, dump_symbols, from_bytes, shadowed_builtins, to_bytes,
+    Return, Value, MAGIC,
+};
+use indexmap::IndexSet;
+use interpret::{ExecResult, Executor, NumericMode, ScriptError};
+use parse::{format_error, parse, parse_all};
 
 use std::env;
 use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
 use std::time::Instant;
 use std::process::exit;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let fp = args.get(1).unwrap_or_else(|| {
-        // identity closure necessary for converting &String to &str
-        let app_name = args.get(0).map_or("bling", |x| x);
-        eprintln!("Error: no source file specified");
-        eprintln!("Usage: {} <source file>", app_name);
+/// Flags which may be given before the source file.
+#[derive(Debug, Default)]
+struct Options {
+    /// Print the value of the program's last expression once it finishes. A program ending in a call to `print` will print `None`, since that is what `print` returns.
+    print_result: bool,
+    /// Only parse and compile the program without running it, to check it for errors.
+    check: bool,
+    /// Print every bytecode operation to stderr as it runs.
+    trace: bool,
+    /// Pause at each call to `breakpoint` to inspect variables.
+    debug: bool,
+    /// Print the identifier table and constant pool after compiling.
+    symbols: bool,
+    /// Raise an error instead of growing numbers past the range of an `isize`.
+    checked_arithmetic: bool,
+    /// The most elements the live lists may hold together, if limited.
+    max_list_len: Option<usize>,
+    /// The most bits an arithmetic result may take up.
+    max_int_bits: Option<u64>,
+    /// Write the compiled bytecode to this file instead of running the program.
+    compile_to: Option<String>,
+    /// Print how many times each op and builtin ran to stderr once the program finishes.
+    profile: bool,
+    /// Print every element of the result rather than only the first [`RESULT_LEN_LIMIT`] of each list.
+    full: bool,
+}
+
+/// How many elements of each list are shown when printing the result.
+const RESULT_LEN_LIMIT: usize = 100;
+
+/// Where the program to run comes from.
+#[derive(Debug, Clone, Copy)]
+enum Source<'a> {
+    /// The path of a source or bytecode file.
+    File(&'a str),
+    /// Source code given on the command line with `--eval`.
+    Inline(&'a str),
+    /// A whole source or bytecode file read from stdin, given as the path `-`.
+    Stdin,
+}
+
+const USAGE_FLAGS: &str = "\
+Flags:
+    -e, --eval <code> Run the given code instead of a source file; script arguments follow it
+    --print-result    Print the value of the last expression in the program
+    --full            Print every element of the result instead of the first 100 of each list
+    --check           Check the program for syntax errors without running it
+    --trace           Print each operation to stderr as it runs
+    --profile         Print how many times each operation and builtin ran to stderr
+    --debug           Pause at each `breakpoint()` to inspect variables from stdin
+    --symbols         Print the compiled identifiers and constants before running
+    --checked-arithmetic
+                      Stop with an error when a number overflows instead of growing it
+    --max-list-len N  Stop with an error when the live lists hold more than N elements in total
+    --max-int-bits N  Stop with an error when arithmetic makes a number with more than N bits
+    --compile         Save the compiled program instead of running it, to the file
+                      given by `-o <file>` after the source file or else with the
+                      extension replaced by `.blc`. Compiled files run like source files";
+
+/// Splits the command line into flags, the program, and the arguments for the script. Exits with a usage message if they are malformed.
+fn parse_args(args: &[String]) -> (Options, Source<'_>, Vec<String>) {
+    // identity closure necessary for converting &String to &str
+    let app_name = args.first().map_or("bling", |x| x);
+    let usage_error = |message: &str| -> ! {
+        eprintln!("Error: {}", message);
+        eprintln!(
+            "Usage: {0} [flags] <source file or - for stdin> [script arguments]\n       {0} [flags] --eval <code> [script arguments]",
+            app_name
+        );
+        eprintln!("{}", USAGE_FLAGS);
         exit(sysexits::USAGE);
-    });
-    let source = fs::read_to_string(fp).unwrap_or_else(|e| {
-        eprintln!("{}", e);
-        exit(sysexits::NO_INPUT);
-    });
-    let ast = parse(&source).unwrap_or_else(|e| {
-        eprintln!("{}", e);
+    };
+    let mut options = Options::default();
+    let mut rest = args.iter().skip(1);
+    let source = loop {
+        match rest.next().map(String::as_str) {
+            Some("--print-result") => options.print_result = true,
+            Some("--full") => options.full = true,
+            Some("--check") => options.check = true,
+            Some("--trace") => options.trace = true,
+            Some("--profile") => options.profile = true,
+            Some("--debug") => options.debug = true,
+            Some("--symbols") => options.symbols = true,
+            Some("--max-int-bits") => match rest.next().map(|n| n.parse()) {
+                Some(Ok(n)) => options.max_int_bits = Some(n),
+                _ => usage_error("`--max-int-bits` expects a non-negative integer"),
+            },
+            Some("--compile") => options.compile_to = Some(String::new()),
+            Some("--checked-arithmetic") => options.checked_arithmetic = true,
+            Some("--max-list-len") => match rest.next().map(|n| n.parse()) {
+                Some(Ok(n)) => options.max_list_len = Some(n),
+                _ => usage_error("`--max-list-len` expects a non-negative integer"),
+            },
+            Some("-e" | "--eval") => match rest.next() {
+                Some(code) => break Source::Inline(code),
+                None => usage_error("`--eval` expects the code to run"),
+            },
+            Some(flag) if flag.starts_with("--") => {
+                usage_error(&format!("unknown flag `{}`", flag));
+            }
+            Some("-") => break Source::Stdin,
+            Some(fp) => break Source::File(fp),
+            None => usage_error("no source file specified"),
+        }
+    };
+    if options.compile_to.is_some() {
+        let output = match (rest.next().map(String::as_str), rest.next(), rest.next()) {
+            (None, _, _) => match source {
+                Source::File(fp) => Path::new(fp).with_extension("blc").display().to_string(),
+                Source::Inline(_) => usage_error("`--compile` with `--eval` needs `-o <file>`"),
+                Source::Stdin => usage_error("`--compile` from stdin needs `-o <file>`"),
+            },
+            (Some("-o"), Some(output), None) => output.clone(),
+            _ => usage_error("`--compile` only accepts `-o <file>` after the source file"),
+        };
+        options.compile_to = Some(output);
+    }
+    // Everything after the source file is passed along to the script.
+    (options, source, rest.cloned().collect())
+}
+
+/// Parses and compiles the program, exiting with the errors if it doesn't parse.
+fn compile_source(source: &str, options: &Options) -> (Code, IndexSet<String>) {
+    // When only checking, report every error rather than just the first.
+    let parsed = if options.check {
+        parse_all(source)
+    } else {
+        parse(source).map_err(|e| vec![e])
+    };
+    let ast = parsed.unwrap_or_else(|errors| {
+        for e in errors {
+            eprintln!("{}\n", format_error(source, &e));
+        }
         exit(sysexits::DATA_ERR);
     });
     //println!("AST ->\n    {:?}", ast);
-    let (bytecode, idents) = compile(ast);
+    if !options.print_result && discards_result(&ast) {
+        eprintln!("Warning: the value of the last expression is discarded; pass `--print-result` to print it\n");
+    }
+    for name in shadowed_builtins(&ast) {
+        eprintln!(
+            "Warning: `{}` is declared or assigned to, which hides the builtin of the same name\n",
+            name
+        );
+    }
+    let (bytecode, idents) = if options.print_result {
+        compile_with(ast, Return::Keep)
+    } else {
+        compile(ast)
+    };
     //println!("BYTECODE ->\n    {:?}", bytecode);
-    let mut exec = Executor::from_code(bytecode, idents);
+    (bytecode, idents)
+}
+
+/// Describes how the script finished, shortening the values carried by errors like the result unless `full` is set.
+fn describe_result(result: &ExecResult<()>, full: bool) -> String {
+    let show = |value: &Value| {
+        if full {
+            value.to_string()
+        } else {
+            value.truncated(RESULT_LEN_LIMIT).to_string()
+        }
+    };
+    match result {
+        Ok(Err(ScriptError::Break(value))) => format!("Ok(Err(Break({})))", show(value)),
+        Ok(Err(ScriptError::AssertionFailed { actual, expected })) => format!(
+            "Ok(Err(AssertionFailed {{ actual: {}, expected: {} }}))",
+            show(actual),
+            show(expected)
+        ),
+        result => format!("{:?}", result),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (options, source, script_args) = parse_args(&args);
+    let file = match source {
+        Source::File(fp) => fs::read(fp).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(sysexits::NO_INPUT);
+        }),
+        Source::Inline(code) => code.as_bytes().to_vec(),
+        Source::Stdin => {
+            let mut file = Vec::new();
+            io::stdin().read_to_end(&mut file).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                exit(sysexits::NO_INPUT);
+            });
+            file
+        }
+    };
+    let (bytecode, idents) = if file.starts_with(MAGIC) {
+        from_bytes(&file).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            exit(sysexits::DATA_ERR);
+        })
+    } else {
+        let source = String::from_utf8(file).unwrap_or_else(|_| {
+            eprintln!("Error: source file is not valid UTF-8");
+            exit(sysexits::DATA_ERR);
+        });
+        compile_source(&source, &options)
+    };
+    if options.symbols {
+        print!("{}", dump_symbols(&bytecode, &idents));
+    }
+    if let Some(output) = &options.compile_to {
+        fs::write(output, to_bytes(&bytecode, &idents)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(sysexits::CANT_CREATE);
+        });
+        return;
+    }
+    if options.check {
+        return;
+    }
+    let mut exec = Executor::from_code(bytecode, idents)
+        .with_args(script_args)
+        .with_system_access(true)
+        .with_trace(options.trace)
+        .with_profile(options.profile)
+        .with_interactive_breakpoints(options.debug)
+        .with_numeric_mode(if options.checked_arithmetic {
+            NumericMode::Checked
+        } else {
+            NumericMode::BigInt
+        })
+        .with_list_limit(options.max_list_len)
+        .with_max_int_bits(options.max_int_bits);
     exec.initialize_builtins();
     //println!("INITIAL EXECUTOR ->\n    {:?}", exec);
     println!("\n=== OUTPUT ===");
     let start_time = Instant::now();
     let result = exec.run();
     let total_time = start_time.elapsed();
+    // A script calling `exit` wants to control the exit code itself, so stay quiet.
+    if let Ok(Err(ScriptError::Exit(code))) = result {
+        exit(code);
+    }
+    if let (true, Ok(Ok(()))) = (options.print_result, &result) {
+        if let Some(value) = exec.take_result() {
+            if options.full {
+                println!("{}", value);
+            } else {
+                println!("{}", value.truncated(RESULT_LEN_LIMIT));
+            }
+        }
+    }
     println!("==============\n");
     //println!("FINISHED EXECUTOR ->\n    {:?}", exec);
-    println!("RESULT ->\n    {:?}", result);
+    println!("RESULT ->\n    {}", describe_result(&result, options.full));
     println!("Time Taken: {}μs", total_time.as_micros());
+    if let Some(profile) = exec.take_profile() {
+        eprint!("{}", profile);
+    }
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            eprintln!("Error: {}", e);
+            for frame in exec.take_traceback().unwrap_or_default() {
+                eprintln!("    {}", frame);
+            }
+            exit(sysexits::DATA_ERR);
+        }
+        Err(e) => {
+            eprintln!("Internal error: {:?}", e);
+            exit(sysexits::SOFTWARE);
+        }
+    }
 }
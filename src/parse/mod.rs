@@ -4,12 +4,13 @@ mod utilities;
 
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{char, digit1},
-    combinator::{all_consuming, cut, map, not, opt, recognize},
+    bytes::complete::{escaped_transform, tag},
+    character::complete::{char, digit1, multispace1, none_of, satisfy},
+    combinator::{all_consuming, cut, map, not, opt, peek, recognize, value},
+    error::{context, ErrorKind, VerboseError, VerboseErrorKind},
     multi::{many0, many1},
-    sequence::{delimited, pair, separated_pair, terminated},
-    Finish, IResult,
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
+    Finish,
 };
 
 use num_bigint::BigInt;
@@ -19,26 +20,52 @@ use utilities::{ident, paren_args, trim_left_ws, trim_right_ws, trim_ws};
 /// Internal representation of a variable identifier.
 pub type Ident = String;
 
+/// A parse error, holding every parser that was in progress when it occurred and what it was looking for.
+pub type ParseError<'a> = VerboseError<&'a str>;
+
+type IResult<'a, O> = nom::IResult<&'a str, O, ParseError<'a>>;
+
 /// Every type of node in the AST.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
+    /// The `none` keyword, whose value is [`Value::None`](crate::compile::Value::None).
+    None,
     /// An integer literal.
     Number(BigInt),
+    /// The negation of a variable, call, or block, written `-x` with no space after the `-`. A `-` directly before digits is instead part of the [`Number`](Expr::Number) literal.
+    Negate(Box<Expr>),
+    /// A string literal within double quotes `""`.
+    String(String),
     /// A variable name of the form `[a-zA-Z_][a-zA-Z_0-9]*`.
     Identifier(Ident),
-    /// An expression being assigned to a variable.
+    /// An expression being assigned to a variable. Compound assignments like `x += 1` are parsed into an assignment of a call, here `x = add(x 1)`.
     Assignment(Ident, Box<Expr>),
     /// A variable being declared and initialized with the value of an expression.
     Declaration(Ident, Box<Expr>),
-    /// A list of expressions within curly brackets `{}`.
+    /// A variable being declared with `let name = value`, replacing any variable of the same name already declared in the current scope instead of erroring.
+    Shadow(Ident, Box<Expr>),
+    /// A list of expressions within curly brackets `{}`. A block is evaluated immediately where it appears; to defer evaluation, such as for the arguments of `while`, use a zero-argument [`Lambda`](Expr::Lambda) instead.
+    ///
+    /// Every block has its own scope. Variables declared inside it are dropped once it ends and may shadow variables of the same name outside it, while assignments reach outward to the nearest enclosing declaration.
     Block(Vec<Expr>),
     /// A function call.
     Application(Box<Expr>, Vec<Expr>),
-    /// A lambda function definition.
-    Lambda(Vec<Ident>, Box<Expr>),
+    /// A lambda function definition. A lambda with no parameters may be written either `() => body` or just `=> body`. Since parentheses followed by `=>` are always a parameter list, `f() => body` is the variable `f` followed by a lambda rather than a bare lambda following a call.
+    Lambda(Params, Box<Expr>),
 }
 
-fn number(input: &str) -> IResult<&str, Expr> {
+/// The parameter list of a lambda, written in the order `(required optional = default rest...)`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Params {
+    /// Parameters which must always be supplied.
+    pub required: Vec<Ident>,
+    /// Parameters which may be left out, along with the expression whose value is used in that case. The default is evaluated each time it is needed, before any of the parameters are declared.
+    pub optional: Vec<(Ident, Expr)>,
+    /// A parameter which collects every argument past the others into a list.
+    pub rest: Option<Ident>,
+}
+
+fn number(input: &str) -> IResult<'_, Expr> {
     map(
         recognize(pair(
             opt(char('-')),
@@ -54,20 +81,85 @@ fn number(input: &str) -> IResult<&str, Expr> {
     )(input)
 }
 
-// fn string(input: &str) -> IResult<&str, Expr> {
-//     delimited(char('"'), , char('"'))
-// }
+fn string(input: &str) -> IResult<'_, Expr> {
+    let escape = alt((
+        value("\\", char('\\')),
+        value("\"", char('"')),
+        value("\n", char('n')),
+        value("\t", char('t')),
+        value("\r", char('r')),
+        value("\0", char('0')),
+    ));
+    map(
+        delimited(
+            char('"'),
+            cut(opt(escaped_transform(none_of("\\\""), '\\', escape))),
+            context("expected closing quote `\"`", cut(char('"'))),
+        ),
+        |s| Expr::String(s.unwrap_or_default()),
+    )(input)
+}
+
+fn negation(input: &str) -> IResult<'_, Expr> {
+    map(
+        preceded(
+            char('-'),
+            context(
+                "expected a value directly after `-`",
+                cut(alt((application, block, none, identifier))),
+            ),
+        ),
+        |operand| Expr::Negate(Box::new(operand)),
+    )(input)
+}
 
-fn identifier(input: &str) -> IResult<&str, Expr> {
+fn none(input: &str) -> IResult<'_, Expr> {
+    // Identifiers that merely start with `none`, like `none_of`, aren't the keyword.
+    let ident_char = satisfy(|c| c.is_ascii_alphanumeric() || c == '_');
+    value(Expr::None, terminated(tag("none"), not(ident_char)))(input)
+}
+
+fn identifier(input: &str) -> IResult<'_, Expr> {
     map(ident, Expr::Identifier)(input)
 }
 
+/// Parses the name of a variable being declared, assigned, shadowed, or taken as a parameter. Keywords can't be bound, and since `none` used to be an ordinary name, trying to is reported as such rather than as a syntax error.
+fn binding(input: &str) -> IResult<'_, Ident> {
+    let (rest, name) = ident(input)?;
+    if name == "none" {
+        let kind =
+            VerboseErrorKind::Context("`none` is a keyword and can't be used as a variable name");
+        return Err(nom::Err::Failure(VerboseError {
+            errors: vec![(input, kind)],
+        }));
+    }
+    Ok((rest, name))
+}
+
+/// Parses the variable on the left of one of the given operators, like the `x` in `x := 1`. The operator is only looked ahead at, so that `none` on its own still parses as a value.
+fn bound_by(operators: &'static [&'static str]) -> impl FnMut(&str) -> IResult<'_, Ident> {
+    move |input| {
+        let (after, _) = ident(input)?;
+        let after = after.trim_start();
+        // `=>` after a name starts a lambda instead.
+        let binds = operators
+            .iter()
+            .any(|op| after.starts_with(op) && !after[op.len()..].starts_with('>'));
+        if !binds {
+            return Err(nom::Err::Error(VerboseError {
+                errors: vec![(input, VerboseErrorKind::Nom(ErrorKind::Tag))],
+            }));
+        }
+        binding(input)
+    }
+}
+
 #[doc(hidden)]
 macro_rules! assign_parse {
     ($name:ident, $variant:ident, $symbol:expr) => {
-        fn $name(input: &str) -> IResult<&str, Expr> {
+        fn $name(input: &str) -> IResult<'_, Expr> {
             map(
-                separated_pair(ident, trim_ws(tag($symbol)), expr),
+                separated_pair(bound_by(&[$symbol]), trim_ws(tag($symbol)), expr),
                 |(lhs, rhs)| Expr::$variant(lhs, Box::new(rhs)),
             )(input)
         }
@@ -77,18 +169,57 @@ macro_rules! assign_parse {
 assign_parse! {assignment, Assignment, "="}
 assign_parse! {declaration, Declaration, ":="}
 
-fn block(input: &str) -> IResult<&str, Expr> {
+/// Parses sugar like `x += 5`, which is short for `x = add(x 5)`.
+fn compound_assignment(input: &str) -> IResult<'_, Expr> {
+    let operator = alt((
+        value("add", tag("+=")),
+        value("sub", tag("-=")),
+        value("mul", tag("*=")),
+        value("div", tag("/=")),
+        value("mod", tag("%=")),
+    ));
     map(
-        delimited(
-            char('{'),
-            cut(many0(trim_left_ws(expr))),
-            cut(trim_left_ws(char('}'))),
+        tuple((
+            bound_by(&["+=", "-=", "*=", "/=", "%="]),
+            trim_ws(operator),
+            expr,
+        )),
+        |(lhs, func, rhs)| {
+            let args = vec![Expr::Identifier(lhs.clone()), rhs];
+            let call = Expr::Application(Box::new(Expr::Identifier(func.to_owned())), args);
+            Expr::Assignment(lhs, Box::new(call))
+        },
+    )(input)
+}
+
+fn shadow(input: &str) -> IResult<'_, Expr> {
+    map(
+        preceded(
+            terminated(tag("let"), multispace1),
+            separated_pair(binding, trim_ws(tag("=")), expr),
         ),
-        Expr::Block,
+        |(lhs, rhs)| Expr::Shadow(lhs, Box::new(rhs)),
     )(input)
 }
 
-fn application(input: &str) -> IResult<&str, Expr> {
+/// Marks where a block with no closing brace was opened, so that [`format_error`] can point it out.
+const UNCLOSED_BLOCK: &str = "unclosed block";
+
+fn block(input: &str) -> IResult<'_, Expr> {
+    let (rest, exprs) = preceded(char('{'), cut(many0(trim_left_ws(expr))))(input)?;
+    let (rest, _) = context("expected closing brace `}`", cut(trim_left_ws(char('}'))))(rest)
+        .map_err(|e| {
+            e.map(|mut error: ParseError<'_>| {
+                error
+                    .errors
+                    .push((input, VerboseErrorKind::Context(UNCLOSED_BLOCK)));
+                error
+            })
+        })?;
+    Ok((rest, Expr::Block(exprs)))
+}
+
+fn application(input: &str) -> IResult<'_, Expr> {
     map(
         pair(
             alt((identifier, block)),
@@ -105,31 +236,174 @@ fn application(input: &str) -> IResult<&str, Expr> {
     )(input)
 }
 
-fn lambda(input: &str) -> IResult<&str, Expr> {
+fn params(input: &str) -> IResult<'_, Params> {
+    let required = terminated(binding, not(alt((tag("..."), trim_left_ws(tag("="))))));
+    let optional = separated_pair(
+        binding,
+        trim_ws(terminated(tag("="), not(char('>')))),
+        context("expected default value", cut(expr)),
+    );
+    let rest = terminated(
+        terminated(binding, tag("...")),
+        context(
+            "a rest parameter must be the last parameter",
+            cut(peek(trim_left_ws(char(')')))),
+        ),
+    );
+    delimited(
+        char('('),
+        map(
+            tuple((
+                many0(trim_left_ws(required)),
+                many0(trim_left_ws(optional)),
+                opt(trim_left_ws(rest)),
+            )),
+            |(required, optional, rest)| Params {
+                required,
+                optional,
+                rest,
+            },
+        ),
+        context(
+            "expected closing parenthesis `)`",
+            cut(trim_left_ws(char(')'))),
+        ),
+    )(input)
+}
+
+fn lambda(input: &str) -> IResult<'_, Expr> {
     map(
-        separated_pair(paren_args(ident), cut(trim_left_ws(tag("=>"))), cut(expr)),
+        separated_pair(
+            // A bare `=>` is sugar for `() =>`.
+            alt((params, map(peek(tag("=>")), |_| Params::default()))),
+            context(
+                "expected `=>` after lambda parameters",
+                cut(trim_left_ws(tag("=>"))),
+            ),
+            context("expected lambda body", cut(expr)),
+        ),
         |(params, body)| Expr::Lambda(params, Box::new(body)),
     )(input)
 }
 
-fn expr(input: &str) -> IResult<&str, Expr> {
+fn expr(input: &str) -> IResult<'_, Expr> {
     trim_left_ws(alt((
         number,
+        negation,
+        string,
         lambda,
-        application,
-        block,
         assignment,
         declaration,
+        compound_assignment,
+        none,
+        shadow,
+        application,
+        block,
         identifier,
     )))(input)
 }
 
-pub fn parse(input: &str) -> Result<Vec<Expr>, nom::error::Error<&str>> {
+pub fn parse(input: &str) -> Result<Vec<Expr>, ParseError<'_>> {
     all_consuming(trim_right_ws(many0(expr)))(input)
         .finish()
         .map(|x| x.1)
 }
 
+/// Parses the whole input like [`parse`], but rather than stopping at the first error, recovers and keeps going so that every error can be reported at once.
+///
+/// After an error, parsing resumes at the start of the next line, skipping any closing braces `}` left over from the broken expression. This is only a heuristic, so errors after the first may occasionally be caused by the first.
+pub fn parse_all(source: &str) -> Result<Vec<Expr>, Vec<ParseError<'_>>> {
+    let mut exprs = vec![];
+    let mut errors = vec![];
+    let mut input = source;
+    loop {
+        input = input.trim_start();
+        if input.is_empty() {
+            break;
+        }
+        match expr(input) {
+            Ok((rest, expr)) => {
+                exprs.push(expr);
+                input = rest;
+            }
+            Err(nom::Err::Error(error) | nom::Err::Failure(error)) => {
+                // Resynchronize from where the error was found, which is always at or after where this expression started.
+                let remaining = error.errors.first().map_or("", |(rest, _)| rest);
+                let after_line = remaining.find('\n').map_or("", |i| &remaining[i + 1..]);
+                input = after_line.trim_start_matches(|c: char| c == '}' || c.is_whitespace());
+                errors.push(error);
+            }
+            Err(nom::Err::Incomplete(_)) => unreachable!("only complete parsers are used"),
+        }
+    }
+    if errors.is_empty() {
+        Ok(exprs)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Returns the line and column numbers of a suffix of the source, counting from 1.
+fn location(source: &str, remaining: &str) -> (usize, usize) {
+    let offset = source.len() - remaining.len();
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_num = source[..line_start].matches('\n').count() + 1;
+    let column = source[line_start..offset].chars().count() + 1;
+    (line_num, column)
+}
+
+/// Renders a parse error as a message pointing at the offending line of the source code with a caret. If a block was left unclosed, where it was opened is noted on the last line.
+pub fn format_error(source: &str, error: &ParseError<'_>) -> String {
+    // The innermost failure comes first. Each entry holds the input that was left unparsed, which is always a suffix of the source.
+    let remaining = error.errors.first().map_or("", |(input, _)| input);
+    let offset = source.len() - remaining.len();
+    let message = error
+        .errors
+        .iter()
+        .find_map(|(_, kind)| match kind {
+            VerboseErrorKind::Context(message) if *message != UNCLOSED_BLOCK => Some(*message),
+            _ => None,
+        })
+        .unwrap_or_else(|| match error.errors.first() {
+            Some((_, VerboseErrorKind::Nom(ErrorKind::Eof))) => "unexpected input",
+            _ => "syntax error",
+        });
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i);
+    let (line_num, column) = location(source, remaining);
+    let line = &source[line_start..line_end];
+    let gutter = " ".repeat(line_num.to_string().len());
+    // Only the outermost unclosed block is noted, since that is where a missing brace was most likely meant to go.
+    let unclosed = error
+        .errors
+        .iter()
+        .rev()
+        .find_map(|(input, kind)| match kind {
+            VerboseErrorKind::Context(UNCLOSED_BLOCK) => Some(location(source, input)),
+            _ => None,
+        });
+    let note = unclosed.map_or(String::new(), |(line_num, column)| {
+        format!(
+            "\nnote: the block opened at line {}, column {} is never closed",
+            line_num, column
+        )
+    });
+    format!(
+        "{} at line {}, column {}\n{} |\n{} | {}\n{} | {}^{}",
+        message,
+        line_num,
+        column,
+        gutter,
+        line_num,
+        line,
+        gutter,
+        " ".repeat(column - 1),
+        note,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,6 +421,268 @@ mod tests {
         );
     }
 
+    #[test]
+    fn string_literal() {
+        use Expr::*;
+        assert_eq!(expr(r#""""#).unwrap().1, String("".to_owned()));
+        assert_eq!(
+            expr(r#""say \"hi\"\n""#).unwrap().1,
+            String("say \"hi\"\n".to_owned())
+        );
+        assert!(parse(r#""unterminated"#).is_err());
+    }
+
+    #[test]
+    fn error_location() {
+        let source = "x := 5\nprint(x\n  y := )\n";
+        let error = parse(source).unwrap_err();
+        assert_eq!(
+            format_error(source, &error),
+            "expected closing parenthesis `)` at line 3, column 5\n  |\n3 |   y := )\n  |     ^"
+        );
+        let source = "f := (x) => {\n    print(x)\n";
+        let error = parse(source).unwrap_err();
+        let message = format_error(source, &error);
+        assert!(message.starts_with("expected closing brace `}` at line 3"));
+        assert!(message.ends_with("\nnote: the block opened at line 1, column 13 is never closed"));
+        // The inner block takes the only closing brace, leaving the outer one unclosed.
+        let source = "x := 1\ny := {\n  z := { 2\n}\n";
+        let error = parse(source).unwrap_err();
+        assert!(format_error(source, &error)
+            .ends_with("block opened at line 2, column 6 is never closed"));
+        let source = "x := { print(x }";
+        let error = parse(source).unwrap_err();
+        assert!(!format_error(source, &error).contains("note:"));
+        let source = "x := 5 )";
+        let error = parse(source).unwrap_err();
+        assert!(format_error(source, &error).starts_with("unexpected input at line 1, column 8"));
+    }
+
+    #[test]
+    fn zero_argument_lambda() {
+        use Expr::*;
+        let thunk = Lambda(Params::default(), Box::new(Identifier("x".to_owned())));
+        assert_eq!(expr("=> x").unwrap().1, thunk);
+        assert_eq!(expr("() => x").unwrap().1, thunk);
+        assert_eq!(
+            expr("{x}").unwrap().1,
+            Block(vec![Identifier("x".to_owned())])
+        );
+        assert_eq!(
+            parse("while(=> x => {x})").unwrap(),
+            vec![Application(
+                Box::new(Identifier("while".to_owned())),
+                vec![
+                    thunk,
+                    Lambda(
+                        Params::default(),
+                        Box::new(Block(vec![Identifier("x".to_owned())]))
+                    )
+                ]
+            )]
+        );
+        assert!(parse("=>").is_err());
+    }
+
+    #[test]
+    fn rest_parameter() {
+        use Expr::*;
+        assert_eq!(
+            expr("(a rest...) => a").unwrap().1,
+            Lambda(
+                Params {
+                    required: vec!["a".to_owned()],
+                    rest: Some("rest".to_owned()),
+                    ..Params::default()
+                },
+                Box::new(Identifier("a".to_owned()))
+            )
+        );
+        assert_eq!(
+            expr("(rest...) => rest").unwrap().1,
+            Lambda(
+                Params {
+                    rest: Some("rest".to_owned()),
+                    ..Params::default()
+                },
+                Box::new(Identifier("rest".to_owned()))
+            )
+        );
+        let source = "(a... b) => a";
+        let error = parse(source).unwrap_err();
+        assert!(format_error(source, &error)
+            .starts_with("a rest parameter must be the last parameter at line 1, column 7"));
+    }
+
+    #[test]
+    fn default_parameter() {
+        use Expr::*;
+        assert_eq!(
+            expr("(a b = 10 c = {x} rest...) => a").unwrap().1,
+            Lambda(
+                Params {
+                    required: vec!["a".to_owned()],
+                    optional: vec![
+                        ("b".to_owned(), Number(10.into())),
+                        ("c".to_owned(), Block(vec![Identifier("x".to_owned())]))
+                    ],
+                    rest: Some("rest".to_owned()),
+                },
+                Box::new(Identifier("a".to_owned()))
+            )
+        );
+        assert!(parse("(a = 1 b) => a").is_err());
+        assert!(parse("(a =) => a").is_err());
+    }
+
+    #[test]
+    fn chained_assignment_is_right_associative() {
+        use Expr::*;
+        assert_eq!(
+            expr("a = b := 5").unwrap().1,
+            Assignment(
+                "a".to_owned(),
+                Box::new(Declaration("b".to_owned(), Box::new(Number(5.into()))))
+            )
+        );
+    }
+
+    #[test]
+    fn none_keyword() {
+        use Expr::*;
+        assert_eq!(expr("none").unwrap().1, None);
+        assert_eq!(parse("none none").unwrap(), [None, None]);
+        assert_eq!(expr("none_of").unwrap().1, Identifier("none_of".to_owned()));
+        assert_eq!(expr("none1").unwrap().1, Identifier("none1".to_owned()));
+        assert_eq!(
+            expr("nonesuch := none").unwrap().1,
+            Declaration("nonesuch".to_owned(), Box::new(None))
+        );
+        let source = "x := 2\nnone := 1";
+        let error = parse(source).unwrap_err();
+        assert!(format_error(source, &error).starts_with(
+            "`none` is a keyword and can't be used as a variable name at line 2, column 1"
+        ));
+        assert!(parse("none = 1").is_err());
+        assert!(parse("none += 1").is_err());
+        assert_eq!(parse("f := (x = none) => x").unwrap().len(), 1);
+        // A thunk following `none` isn't an assignment to it.
+        assert_eq!(
+            expr("f(none => x)").unwrap().1,
+            Application(
+                Box::new(Identifier("f".to_owned())),
+                vec![
+                    None,
+                    Lambda(Params::default(), Box::new(Identifier("x".to_owned())))
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn none_cannot_be_bound() {
+        use Expr::*;
+        let keyword = "`none` is a keyword and can't be used as a variable name";
+        for source in [
+            "let none = 1",
+            "f := (none) => none",
+            "(x none = 1) => x",
+            "(none...) => 1",
+        ] {
+            let error = parse(source).unwrap_err();
+            assert!(
+                format_error(source, &error).starts_with(keyword),
+                "{:?} gave {:?}",
+                source,
+                format_error(source, &error)
+            );
+        }
+        assert_eq!(expr("-none").unwrap().1, Negate(Box::new(None)));
+    }
+
+    #[test]
+    fn compound_assignment_desugars() {
+        use Expr::*;
+        let call = |func: &str, rhs| {
+            Box::new(Application(
+                Box::new(Identifier(func.to_owned())),
+                vec![Identifier("x".to_owned()), rhs],
+            ))
+        };
+        assert_eq!(
+            expr("x += 5").unwrap().1,
+            Assignment("x".to_owned(), call("add", Number(5.into())))
+        );
+        assert_eq!(
+            expr("x%=y").unwrap().1,
+            Assignment("x".to_owned(), call("mod", Identifier("y".to_owned())))
+        );
+        assert!(parse("x + 5").is_err());
+    }
+
+    #[test]
+    fn let_shadowing() {
+        use Expr::*;
+        assert_eq!(
+            expr("let x = 5").unwrap().1,
+            Shadow("x".to_owned(), Box::new(Number(5.into())))
+        );
+        assert_eq!(
+            expr("letter = 5").unwrap().1,
+            Assignment("letter".to_owned(), Box::new(Number(5.into())))
+        );
+    }
+
+    #[test]
+    fn unary_minus() {
+        use Expr::*;
+        assert_eq!(expr("-5").unwrap().1, Number((-5).into()));
+        assert_eq!(expr("-0").unwrap().1, Number(0.into()));
+        assert_eq!(
+            expr("-x").unwrap().1,
+            Negate(Box::new(Identifier("x".to_owned())))
+        );
+        assert_eq!(
+            expr("-f(1)").unwrap().1,
+            Negate(Box::new(Application(
+                Box::new(Identifier("f".to_owned())),
+                vec![Number(1.into())]
+            )))
+        );
+        assert!(parse("- 5").is_err());
+        assert!(parse("-").is_err());
+        // There is no infix subtraction; use `sub(5 3)`.
+        assert!(parse("5 - 3").is_err());
+    }
+
+    #[test]
+    fn recover_from_errors() {
+        let source = "x := 5\nf := (x) => {\n    print(x\n}\ny := 6\nz := )\nprint(y)\n";
+        let errors = parse_all(source).unwrap_err();
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|error| {
+                format_error(source, error)
+                    .lines()
+                    .next()
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "expected closing parenthesis `)` at line 4, column 1",
+                "syntax error at line 6, column 3",
+            ]
+        );
+        assert_eq!(
+            parse_all("x := 5 print(x)"),
+            parse("x := 5 print(x)").map_err(|_| vec![])
+        );
+        assert_eq!(parse_all("  \n"), Ok(vec![]));
+    }
+
     #[test]
     fn multiple_application() {
         use Expr::*;
@@ -5,40 +5,40 @@ use nom::{
     bytes::complete::tag,
     character::complete::{alpha1, alphanumeric1, char, multispace0},
     combinator::{cut, map, recognize},
-    error::ParseError,
+    error::{context, ContextError, ParseError},
     multi::many0,
     sequence::{delimited, pair, preceded, terminated},
     IResult,
 };
 
-pub fn trim_ws<'a, F: 'a, O, E: ParseError<&'a str>>(
+pub fn trim_ws<'a, F, O, E: ParseError<&'a str>>(
     inner: F,
 ) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
 where
-    F: FnMut(&'a str) -> IResult<&'a str, O, E>,
+    F: 'a + FnMut(&'a str) -> IResult<&'a str, O, E>,
 {
     delimited(multispace0, inner, multispace0)
 }
 
-pub fn trim_left_ws<'a, F: 'a, O, E: ParseError<&'a str>>(
+pub fn trim_left_ws<'a, F, O, E: ParseError<&'a str>>(
     inner: F,
 ) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
 where
-    F: FnMut(&'a str) -> IResult<&'a str, O, E>,
+    F: 'a + FnMut(&'a str) -> IResult<&'a str, O, E>,
 {
     preceded(multispace0, inner)
 }
 
-pub fn trim_right_ws<'a, F: 'a, O, E: ParseError<&'a str>>(
+pub fn trim_right_ws<'a, F, O, E: ParseError<&'a str>>(
     inner: F,
 ) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
 where
-    F: FnMut(&'a str) -> IResult<&'a str, O, E>,
+    F: 'a + FnMut(&'a str) -> IResult<&'a str, O, E>,
 {
     terminated(inner, multispace0)
 }
 
-pub fn ident(input: &str) -> IResult<&str, String> {
+pub fn ident<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, String, E> {
     map(
         recognize(pair(
             alt((alpha1, tag("_"))),
@@ -48,14 +48,18 @@ pub fn ident(input: &str) -> IResult<&str, String> {
     )(input)
 }
 
-pub fn paren_args<'a, F: 'a, I: 'a>(inner: F) -> impl FnMut(&'a str) -> IResult<&str, Vec<I>>
+pub fn paren_args<'a, F, I: 'a, E>(inner: F) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<I>, E>
 where
-    F: FnMut(&str) -> IResult<&str, I>,
+    F: 'a + FnMut(&'a str) -> IResult<&'a str, I, E>,
+    E: 'a + ParseError<&'a str> + ContextError<&'a str>,
 {
     delimited(
         char('('),
         cut(many0(trim_left_ws(inner))),
-        cut(trim_left_ws(char(')'))),
+        context(
+            "expected closing parenthesis `)`",
+            cut(trim_left_ws(char(')'))),
+        ),
     )
 }
 
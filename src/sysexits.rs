@@ -7,4 +7,8 @@ pub const USAGE: i32 = 64;
 pub const DATA_ERR: i32 = 65;
 ///  An input file (not a system file) did not exist orwas not readable. This could also include errors like "No message" to a mailer (if it cared to catch it).
 pub const NO_INPUT: i32 = 66;
+/// An internal software error has been detected. This should be limited to non-operating system related errors if possible.
+pub const SOFTWARE: i32 = 70;
+/// A (user specified) output file cannot be created.
+pub const CANT_CREATE: i32 = 73;
 // TODO: Complete this
@@ -0,0 +1,148 @@
+//! Runs the interpreter binary the way a user would.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn bling(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bling"))
+        .args(args)
+        .output()
+        .expect("the interpreter should start")
+}
+
+#[test]
+fn eval_runs_inline_code() {
+    let output = bling(&["-e", "print(add(1 2)) print(at(args() 0))", "arg"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\n3\narg\n"), "stdout was {:?}", stdout);
+}
+
+#[test]
+fn eval_reports_errors_like_files() {
+    let output = bling(&["--eval", "print("]);
+    assert_eq!(output.status.code(), Some(65));
+    let output = bling(&["--eval", "add(1 list())"]);
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("argument has the wrong type"),
+        "stderr was {:?}",
+        stderr
+    );
+    assert_eq!(bling(&["--eval"]).status.code(), Some(64));
+}
+
+#[test]
+fn dash_reads_program_from_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bling"))
+        .args(["-", "arg"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("the interpreter should start");
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"x := mul(6 7)\nprint(x)\nprint(at(args() 0))\n")
+        .unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\n42\narg\n"), "stdout was {:?}", stdout);
+}
+
+#[test]
+fn internal_errors_are_reported() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bling"))
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("the interpreter should start");
+    // Bytecode that decodes fine, but drops a value from an empty stack.
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"BLC\x01\x00\x01\x03\x00\x00\x00\x00\x00")
+        .unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(70));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Internal error: StackUnderflow"),
+        "stderr was {:?}",
+        stderr
+    );
+}
+
+#[test]
+fn empty_program_succeeds() {
+    let output = bling(&["--print-result", "-e", " \n"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("=== OUTPUT ===\nNone\n"),
+        "stdout was {:?}",
+        stdout
+    );
+}
+
+#[test]
+fn warns_when_shadowing_a_builtin() {
+    let output = bling(&["-e", "print := 3"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Warning: `print` is declared or assigned to"),
+        "stderr was {:?}",
+        stderr
+    );
+}
+
+#[test]
+fn result_line_truncates_error_values() {
+    let output = bling(&["-e", "break(replicate(1000 0))"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("RESULT ->\n    Ok(Err(Break([0, 0,")
+            && stdout.contains("... (900 more)])))"),
+        "stdout was {:?}",
+        stdout
+    );
+    let output = bling(&["--full", "-e", "break(replicate(1000 0))"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("more)"), "stdout was {:?}", stdout);
+}
+
+#[test]
+fn examples_run() {
+    let mut dirs = vec![std::path::PathBuf::from(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/examples"
+    ))];
+    let mut count = 0;
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "bli") {
+                let output = Command::new(env!("CARGO_BIN_EXE_bling"))
+                    .arg(&path)
+                    .stdin(Stdio::null())
+                    .output()
+                    .expect("the interpreter should start");
+                assert!(
+                    output.status.success(),
+                    "{} failed: {}",
+                    path.display(),
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                count += 1;
+            }
+        }
+    }
+    assert!(count > 0, "no examples found");
+}